@@ -0,0 +1,402 @@
+//! Controls over the command's process: how long it may run and the state
+//! it starts in.
+
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::debug_eprintln;
+
+/// Exit code when the command was stopped for exceeding `--timeout`, the
+/// same as coreutils' `timeout`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// How often a command with a timeout is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A resource limit that can be set on the command (`--limit-*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Open file descriptors.
+    Nofile,
+    /// Size of core dumps, in bytes.
+    Core,
+    /// Size of the address space, in bytes.
+    As,
+    /// Processes of the user.
+    Nproc,
+    /// CPU time, in seconds.
+    Cpu,
+}
+
+/// Parses a resource limit: a number, optionally with a `K`, `M` or `G`
+/// suffix (powers of 1024), or `unlimited`.
+pub fn parse_limit(spec: &str) -> Result<u64, String> {
+    if spec == "unlimited" {
+        return Ok(libc::RLIM_INFINITY);
+    }
+    let (number, multiplier) = match spec.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&spec[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&spec[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&spec[..i], 1 << 30),
+        _ => (spec, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!(
+                "expected a number like 1024 or 512M, or unlimited, got '{}'",
+                spec
+            )
+        })
+}
+
+/// An I/O scheduling class and priority level (`--ionice`), as in ioprio_set(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    class: u16,
+    level: u16,
+}
+
+impl IoPriority {
+    /// The value ioprio_set(2) takes.
+    #[cfg(target_os = "linux")]
+    fn value(self) -> libc::c_int {
+        const IOPRIO_CLASS_SHIFT: u16 = 13;
+        libc::c_int::from(self.class << IOPRIO_CLASS_SHIFT | self.level)
+    }
+}
+
+/// Parses an I/O priority like ionice(1): `realtime[:LEVEL]`,
+/// `best-effort[:LEVEL]` or `idle`, or the class numbers 1 to 3. The level
+/// runs from 0 (highest) to 7 and defaults to 4.
+pub fn parse_io_priority(spec: &str) -> Result<IoPriority, String> {
+    let (class, level) = match spec.split_once(':') {
+        Some((class, level)) => (class, Some(level)),
+        None => (spec, None),
+    };
+    let class = match class {
+        "realtime" | "1" => 1,
+        "best-effort" | "2" => 2,
+        "idle" | "3" => 3,
+        _ => {
+            return Err(format!(
+                "unknown class '{}' (use realtime, best-effort or idle)",
+                class
+            ))
+        }
+    };
+    let level = match level {
+        None => 4,
+        Some(_) if class == 3 => return Err("the idle class takes no level".to_string()),
+        Some(level) => match level.parse() {
+            Ok(level @ 0..=7) => level,
+            _ => return Err(format!("expected a level from 0 to 7, got '{}'", level)),
+        },
+    };
+    Ok(IoPriority { class, level })
+}
+
+/// Process state the command starts in, set up between fork and exec.
+#[derive(Debug, Clone, Default)]
+pub struct Setup {
+    /// Linux: have the kernel kill the command when rbwchain exits.
+    pub kill_on_parent_exit: bool,
+    /// The user id to switch to.
+    pub uid: Option<u32>,
+    /// The group id to switch to.
+    pub gid: Option<u32>,
+    /// The supplementary groups replacing rbwchain's, set with `uid`.
+    pub groups: Option<Vec<u32>>,
+    /// Resource limits, set as both the soft and the hard limit.
+    pub limits: Vec<(Limit, u64)>,
+    /// The niceness to run at.
+    pub nice: Option<i32>,
+    /// Linux: the I/O scheduling class and level.
+    pub io_priority: Option<IoPriority>,
+    /// Linux: the adjustment of the OOM killer's badness score.
+    pub oom_score_adj: Option<i32>,
+    /// The file mode creation mask.
+    pub umask: Option<u32>,
+}
+
+impl Setup {
+    /// Registers the setup to run in the command's process before exec.
+    pub fn apply(self, command: &mut Command) {
+        let parent = std::process::id() as libc::pid_t;
+        // SAFETY: The hook only makes async-signal-safe system calls and
+        // does not allocate.
+        unsafe {
+            command.pre_exec(move || self.run(parent));
+        }
+    }
+
+    fn run(&self, parent: libc::pid_t) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if self.kill_on_parent_exit {
+            // SAFETY: prctl and getppid have no memory safety preconditions.
+            unsafe {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                // rbwchain may have exited before the signal was armed
+                if libc::getppid() != parent {
+                    return Err(io::Error::from_raw_os_error(libc::ESRCH));
+                }
+            }
+        }
+        let _ = parent;
+
+        // Before switching users, who might not be allowed to raise limits or
+        // priorities
+        for &(limit, value) in &self.limits {
+            let resource = match limit {
+                Limit::Nofile => libc::RLIMIT_NOFILE,
+                Limit::Core => libc::RLIMIT_CORE,
+                Limit::As => libc::RLIMIT_AS,
+                Limit::Nproc => libc::RLIMIT_NPROC,
+                Limit::Cpu => libc::RLIMIT_CPU,
+            };
+            let rlimit = libc::rlimit {
+                rlim_cur: value,
+                rlim_max: value,
+            };
+            // SAFETY: `rlimit` is a valid, initialized struct.
+            if unsafe { libc::setrlimit(resource, &rlimit) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(nice) = self.nice {
+            // SAFETY: setpriority has no memory safety preconditions.
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(priority) = self.io_priority {
+            const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+            // SAFETY: ioprio_set takes no pointers.
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_ioprio_set,
+                    IOPRIO_WHO_PROCESS,
+                    0,
+                    priority.value(),
+                )
+            };
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(adjustment) = self.oom_score_adj {
+            set_oom_score_adj(adjustment)?;
+        }
+
+        if let Some(mask) = self.umask {
+            // SAFETY: umask cannot fail.
+            unsafe { libc::umask(mask as libc::mode_t) };
+        }
+
+        // SAFETY: Plain system calls; `groups` outlives the call. Groups
+        // must be changed while still privileged, so before the user.
+        unsafe {
+            if let Some(groups) = &self.groups {
+                if libc::setgroups(groups.len() as _, groups.as_ptr()) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(gid) = self.gid {
+                if libc::setgid(gid) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(uid) = self.uid {
+                if libc::setuid(uid) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `adjustment` to /proc/self/oom_score_adj with plain system calls,
+/// as std's file handling may allocate, which is not allowed before exec.
+#[cfg(target_os = "linux")]
+fn set_oom_score_adj(adjustment: i32) -> io::Result<()> {
+    use std::io::Write;
+
+    // Formatting an integer into a slice does not allocate
+    let mut buffer = [0u8; 12];
+    let mut rest = &mut buffer[..];
+    write!(rest, "{}", adjustment)?;
+    let length = 12 - rest.len();
+
+    let path = b"/proc/self/oom_score_adj\0";
+    // SAFETY: `path` is NUL-terminated and `buffer` holds `length` bytes.
+    unsafe {
+        let fd = libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = libc::write(fd, buffer.as_ptr().cast(), length);
+        let error = io::Error::last_os_error();
+        libc::close(fd);
+        if written == -1 {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a duration such as `300`, `300s`, `5m`, `1h` or `500ms`. A bare
+/// number is in seconds.
+pub fn parse_duration(spec: &str) -> Result<Duration, String> {
+    let split = spec
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(spec.len());
+    let (number, unit) = spec.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration like 30s, 5m or 1h, got '{}'", spec))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => {
+            return Err(format!(
+                "unknown unit '{}' in '{}' (use ms, s, m or h)",
+                unit, spec
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration '{}': {}", spec, e))
+}
+
+/// Linux: makes rbwchain the reaper of the orphans among the command's
+/// descendants (PR_SET_CHILD_SUBREAPER), as PID 1 is in a container.
+#[cfg(target_os = "linux")]
+pub fn become_subreaper() -> io::Result<()> {
+    // SAFETY: prctl has no memory safety preconditions.
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Collects the exit status of `child` once it has exited, or returns None
+/// if it is still running and not `block`ing. With `reap`, any other exited
+/// process is collected along the way: as an init, rbwchain inherits the
+/// orphans of the command's descendants, which would linger as zombies.
+fn collect(child: &mut Child, reap: bool, block: bool) -> io::Result<Option<ExitStatus>> {
+    if !reap {
+        return if block {
+            child.wait().map(Some)
+        } else {
+            child.try_wait()
+        };
+    }
+    let flags = if block { 0 } else { libc::WNOHANG };
+    loop {
+        let mut status = 0;
+        // SAFETY: `status` is a valid place for waitpid to write to.
+        match unsafe { libc::waitpid(-1, &mut status, flags) } {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            0 => return Ok(None),
+            pid if pid as u32 == child.id() => return Ok(Some(ExitStatus::from_raw(status))),
+            // An orphan, now reaped
+            _ => {}
+        }
+    }
+}
+
+/// Waits for `child` to exit, reaping other processes with `reap`.
+pub fn wait(child: &mut Child, reap: bool) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = collect(child, reap, true)? {
+            return Ok(status);
+        }
+    }
+}
+
+/// Waits for `child`, which leads its own process group. After `timeout`,
+/// the group receives SIGTERM, and SIGKILL if it is still running `grace`
+/// later. Returns the exit status and whether the timeout expired. Other
+/// processes are reaped with `reap`.
+pub fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+    grace: Duration,
+    reap: bool,
+    debug: bool,
+) -> io::Result<(ExitStatus, bool)> {
+    let group = -(child.id() as libc::pid_t);
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = collect(child, reap, false)? {
+            return Ok((status, false));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    debug_eprintln(
+        debug,
+        format_args!("Timeout expired, sending SIGTERM to the command."),
+    );
+    // SAFETY: kill has no memory safety preconditions.
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if let Some(status) = collect(child, reap, false)? {
+            return Ok((status, true));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    debug_eprintln(
+        debug,
+        format_args!("The command is still running, sending SIGKILL."),
+    );
+    // SAFETY: As above.
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    Ok((wait(child, reap)?, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("300"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration(".5s"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for spec in ["", "s", "-1s", "1.2.3s", "5 m", "5d", "1e3"] {
+            assert!(parse_duration(spec).is_err(), "{}", spec);
+        }
+        assert!(parse_duration("5d")
+            .unwrap_err()
+            .contains("unknown unit 'd'"));
+        assert!(parse_duration("99999999999999999999999h").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Serialization of parsed note variables into the config file formats of
+//! `--file-format`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use clap::ValueEnum;
+
+/// The syntax a `-f` file is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    /// A JSON object of strings.
+    Json,
+    /// A YAML mapping of strings.
+    Yaml,
+    /// `KEY=VALUE` lines, quoted where needed.
+    Dotenv,
+}
+
+/// Writes `vars` in `format`, keys sorted.
+pub fn render(vars: HashMap<String, String>, format: FileFormat) -> Result<String, String> {
+    let vars: BTreeMap<String, String> = vars.into_iter().collect();
+    match format {
+        FileFormat::Json => serde_json::to_string_pretty(&vars)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        FileFormat::Yaml => serde_yaml::to_string(&vars).map_err(|e| e.to_string()),
+        FileFormat::Dotenv => Ok(vars
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, dotenv_value(value)))
+            .collect()),
+    }
+}
+
+/// Quotes `value` for a dotenv file unless it is made of characters every
+/// dotenv reader takes literally. Single quotes keep the value verbatim;
+/// values that cannot be single-quoted get double quotes with escapes, `$`
+/// included so that it is not taken for a reference.
+fn dotenv_value(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:@+,=%".contains(c));
+    if plain {
+        return value.to_string();
+    }
+    if !value.contains(['\'', '\n']) {
+        return format!("'{}'", value);
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '$' => quoted.push_str("\\$"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{self, Format, ParseOptions};
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dotenv_quoting() {
+        assert_eq!(dotenv_value("plain-1.2/x:y@z"), "plain-1.2/x:y@z");
+        assert_eq!(dotenv_value(""), "''");
+        assert_eq!(dotenv_value("a b"), "'a b'");
+        assert_eq!(dotenv_value("$HOME"), "'$HOME'");
+        assert_eq!(dotenv_value("it's"), "\"it's\"");
+        assert_eq!(dotenv_value("a\nb"), "\"a\\nb\"");
+        assert_eq!(dotenv_value("it's \"$x\" \\"), "\"it's \\\"\\$x\\\" \\\\\"");
+    }
+
+    #[test]
+    fn dotenv_round_trip() {
+        let values = vars(&[
+            ("PLAIN", "value"),
+            ("EMPTY", ""),
+            ("SPACES", "  padded  "),
+            ("QUOTE", "it's"),
+            ("DOUBLE", "say \"hi\""),
+            ("DOLLAR", "pa$word ${HOME} $$"),
+            ("BOTH", "it's ${HOME} and $USER"),
+            ("NEWLINES", "line one\nline 'two'\n${HOME}\n"),
+            ("BACKSLASH", "C:\\temp\\n"),
+            ("COMMENT", "a # b"),
+        ]);
+        let rendered = render(values.clone(), FileFormat::Dotenv).unwrap();
+        let options = ParseOptions {
+            debug: false,
+            format: Format::Env,
+            interpolate: true,
+            expand_env: false,
+            preserve_whitespace: false,
+            strict: true,
+            section: None,
+            flatten_separator: "_".to_string(),
+            flatten_depth: None,
+        };
+        let parsed = parse::parse_env_vars(&rendered, &options).unwrap();
+        assert_eq!(parsed, values, "in\n{}", rendered);
+    }
+
+    #[test]
+    fn json_and_yaml_are_sorted() {
+        let values = vars(&[("B", "2"), ("A", "it's")]);
+        assert_eq!(
+            render(values.clone(), FileFormat::Json).unwrap(),
+            "{\n  \"A\": \"it's\",\n  \"B\": \"2\"\n}\n"
+        );
+        assert_eq!(
+            render(values, FileFormat::Yaml).unwrap(),
+            "A: it's\nB: '2'\n"
+        );
+    }
+}
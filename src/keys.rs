@@ -0,0 +1,197 @@
+//! Selection and renaming of parsed keys on their way into the child's
+//! environment.
+
+/// A case conversion applied to injected keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+/// Rules applied to every parsed key before it is injected.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRules {
+    /// If not empty, only keys matching one of these globs are injected.
+    pub only: Vec<String>,
+    /// Keys matching one of these globs are never injected.
+    pub exclude: Vec<String>,
+    /// Renames specific keys (`--map SRC=DST`), matched as parsed.
+    pub renames: Vec<(String, String)>,
+    /// Removed from the start of keys that have it and are not renamed.
+    pub strip_prefix: Option<String>,
+    /// Prepended to every injected key.
+    pub prefix: Option<String>,
+    /// Converts the final name, prefix included.
+    pub case: Option<Case>,
+}
+
+impl KeyRules {
+    /// Returns the name under which `key` is injected, or `None` if it is
+    /// filtered out. Filters see the key as parsed, before any renaming.
+    pub fn apply(&self, key: &str) -> Option<String> {
+        let selected = self.only.is_empty() || self.only.iter().any(|p| glob_match(p, key));
+        let excluded = self.exclude.iter().any(|p| glob_match(p, key));
+        if !selected || excluded {
+            return None;
+        }
+
+        let key = match (self.rename(key), &self.strip_prefix) {
+            (Some(renamed), _) => renamed,
+            (None, Some(strip)) if self.strips(key) => &key[strip.len()..],
+            (None, _) => key,
+        };
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        let name = format!("{}{}", prefix, key);
+        Some(match self.case {
+            Some(Case::Upper) => name.to_uppercase(),
+            Some(Case::Lower) => name.to_lowercase(),
+            None => name,
+        })
+    }
+
+    /// Whether `key` is renamed or has a prefix stripped. Such keys are
+    /// injected after the others, so they override a plain key of the same
+    /// name (`PROD_DB_URL` a plain `DB_URL`, with `--strip-prefix PROD_`).
+    pub fn overrides(&self, key: &str) -> bool {
+        self.rename(key).is_some() || self.strips(key)
+    }
+
+    fn rename(&self, key: &str) -> Option<&str> {
+        self.renames
+            .iter()
+            .rev() // The last mapping of a key wins
+            .find(|(source, _)| source == key)
+            .map(|(_, target)| target.as_str())
+    }
+
+    fn strips(&self, key: &str) -> bool {
+        self.strip_prefix
+            .as_deref()
+            .is_some_and(|p| key.len() > p.len() && key.starts_with(p))
+    }
+}
+
+/// Parses a `--map SRC=DST` rename.
+pub fn parse_rename(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((source, target)) if !source.is_empty() && !target.is_empty() => {
+            Ok((source.to_string(), target.to_string()))
+        }
+        _ => Err(format!("expected SRC=DST, got '{}'", spec)),
+    }
+}
+
+/// Matches `name` against a glob where `*` stands for any run of characters
+/// and `?` for a single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Iterative matching with backtracking to the most recent '*'
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    // Let the '*' swallow one more character
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("DB_*", "DB_URL"));
+        assert!(glob_match("DB_*", "DB_"));
+        assert!(glob_match("*_URL", "DB_URL"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("A?C", "ABC"));
+        assert!(glob_match("*B*B*", "ABBAB"));
+        assert!(!glob_match("A?C", "AC"));
+        assert!(!glob_match("DB_*", "XDB_URL"));
+        assert!(!glob_match("*_URL", "DB_URLS"));
+        assert!(!glob_match("db_*", "DB_URL"));
+    }
+
+    #[test]
+    fn only_and_exclude_filter_parsed_keys() {
+        let rules = KeyRules {
+            only: strings(&["DB_*", "API_KEY"]),
+            exclude: strings(&["*_PASSWORD"]),
+            prefix: Some("APP_".to_string()),
+            ..KeyRules::default()
+        };
+        assert_eq!(rules.apply("DB_URL").as_deref(), Some("APP_DB_URL"));
+        assert_eq!(rules.apply("API_KEY").as_deref(), Some("APP_API_KEY"));
+        assert_eq!(rules.apply("DB_PASSWORD"), None);
+        assert_eq!(rules.apply("OTHER"), None);
+    }
+
+    #[test]
+    fn renames_win_over_strip_prefix() {
+        let rules = KeyRules {
+            renames: vec![
+                ("PROD_DB".to_string(), "FIRST".to_string()),
+                ("PROD_DB".to_string(), "DATABASE".to_string()),
+            ],
+            strip_prefix: Some("PROD_".to_string()),
+            ..KeyRules::default()
+        };
+        assert_eq!(rules.apply("PROD_DB").as_deref(), Some("DATABASE"));
+        assert_eq!(rules.apply("PROD_URL").as_deref(), Some("URL"));
+        assert_eq!(rules.apply("PROD_").as_deref(), Some("PROD_"));
+        assert_eq!(rules.apply("URL").as_deref(), Some("URL"));
+        assert!(rules.overrides("PROD_DB"));
+        assert!(rules.overrides("PROD_URL"));
+        assert!(!rules.overrides("URL"));
+    }
+
+    #[test]
+    fn case_applies_to_the_prefix_too() {
+        let upper = KeyRules {
+            prefix: Some("app_".to_string()),
+            case: Some(Case::Upper),
+            ..KeyRules::default()
+        };
+        assert_eq!(upper.apply("db_url").as_deref(), Some("APP_DB_URL"));
+        let lower = KeyRules {
+            case: Some(Case::Lower),
+            ..KeyRules::default()
+        };
+        assert_eq!(lower.apply("DB_URL").as_deref(), Some("db_url"));
+    }
+
+    #[test]
+    fn rename_specs() {
+        assert_eq!(
+            parse_rename("SRC=DST=X"),
+            Ok(("SRC".to_string(), "DST=X".to_string()))
+        );
+        assert!(parse_rename("SRC").is_err());
+        assert!(parse_rename("=DST").is_err());
+        assert!(parse_rename("SRC=").is_err());
+    }
+}
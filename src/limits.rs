@@ -0,0 +1,76 @@
+//! Checks against the kernel's limits on the size of argv and environment,
+//! so huge notes fail with an explanation instead of a bare `E2BIG`.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+
+/// Bytes a string occupies on the new process' stack: its content, the
+/// terminating NUL and the pointer to it.
+fn footprint(length: usize) -> usize {
+    length + 1 + size_of::<*const u8>()
+}
+
+/// The total size allowed for argv plus environment (`ARG_MAX`).
+fn arg_max() -> Option<usize> {
+    // SAFETY: sysconf has no preconditions.
+    let limit = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    usize::try_from(limit).ok().filter(|&l| l > 0)
+}
+
+/// The size allowed for a single argument or `KEY=VALUE` string
+/// (`MAX_ARG_STRLEN`, 32 pages). Only Linux has such a limit.
+fn max_string_length() -> Option<usize> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    // SAFETY: sysconf has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(page_size).ok().map(|p| p * 32)
+}
+
+/// Verifies that `argv` plus the child's environment, i.e. the `inherited`
+/// environment with `injected` applied on top, fit the kernel's limits.
+pub fn check_exec_size(
+    argv: &[OsString],
+    inherited: &HashMap<OsString, OsString>,
+    injected: &HashMap<OsString, OsString>,
+) -> Result<(), String> {
+    let mut environment = inherited.clone();
+    environment.extend(injected.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    if let Some(max) = max_string_length() {
+        for (key, value) in &environment {
+            let length = key.len() + 1 + value.len();
+            if length + 1 > max {
+                return Err(format!(
+                    "The variable '{}' is {} bytes long, more than the {} bytes the system allows for a single variable. Use file mode (-f) for content this large.",
+                    key.to_string_lossy(),
+                    length,
+                    max - 1
+                ));
+            }
+        }
+        if let Some(argument) = argv.iter().find(|a| a.len() + 1 > max) {
+            return Err(format!(
+                "An argument of the command is {} bytes long, more than the {} bytes the system allows.",
+                argument.len(),
+                max - 1
+            ));
+        }
+    }
+
+    if let Some(max) = arg_max() {
+        let total: usize = environment
+            .iter()
+            .map(|(key, value)| footprint(key.len() + 1 + value.len()))
+            .chain(argv.iter().map(|a| footprint(a.len())))
+            .sum();
+        if total > max {
+            return Err(format!(
+                "The command line and environment take {} bytes, more than the system limit (ARG_MAX) of {} bytes. Use file mode (-f) to pass large notes.",
+                total, max
+            ));
+        }
+    }
+    Ok(())
+}
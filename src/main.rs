@@ -1,20 +1,52 @@
 #![allow(clippy::needless_return)] // Style preference for clarity in this case
 
+use base64::prelude::{Engine, BASE64_STANDARD};
 use clap::Parser;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::env;
 use std::error::Error;
-use std::ffi::OsString;
-use std::io::Write;
-use std::os::unix::process::ExitStatusExt;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::{ErrorKind, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
 use std::str;
-use tempfile::{Builder, NamedTempFile};
-use which::which;
+use std::thread;
+use std::time::Duration;
+use tempfile::{Builder, NamedTempFile, TempDir, TempPath};
+
+mod child;
+mod convert;
+mod keys;
+mod limits;
+mod parallel;
+mod parse;
+mod pem;
+mod perms;
+mod picker;
+mod pipe;
+mod procfile;
+mod refresh;
+mod serve;
+mod signals;
+mod source;
+mod suggest;
+mod supervise;
+mod template;
+mod validate;
+mod watch;
 
 // --- Constants ---
 const RBWCHAIN_PREFIX: &str = "[rbwchain]";
 
+/// Environment variables as ordered `(name, value)` pairs.
+type EnvPairs = Vec<(String, String)>;
+
 // --- Logging Abstraction ---
 
 /// Prints a debug message to stderr if debug mode is enabled.
@@ -44,84 +76,911 @@ fn error_eprintln(args: std::fmt::Arguments) {
 
 // --- Core Logic ---
 
-/// Executes `rbw <secret_note>` and parses its stdout for environment variables.
-/// Expects stdout to contain lines in the format "KEY=VALUE".
-/// Returns a HashMap of the parsed variables.
-fn get_secret_content_from_rbw(secret_note: &str) -> Result<String, Box<dyn Error>> {
-    let rbw_cmd_display = format!("rbw get {}", secret_note); // For error messages
-    let output = Command::new("rbw")
-        .arg("get")
-        .arg(secret_note)
-        .stdout(Stdio::piped()) // Capture stdout
-        .stderr(Stdio::piped()) // Capture stderr for error reporting
-        .output() // Execute and wait
-        .map_err(|e| format!("Failed to execute '{}': {}", rbw_cmd_display, e))?;
-
-    // Check if the command executed successfully
-    if !output.status.success() {
-        let stderr_output = String::from_utf8_lossy(&output.stderr);
-        // Use the dedicated error printer
-        error_eprintln(format_args!(
-            "Command '{}' failed with status {}: {}",
-            rbw_cmd_display,
-            output.status,
-            stderr_output.trim()
-        ));
-        return Err(format!(
-            "Command '{}' failed with status {}: {}",
-            rbw_cmd_display,
-            output.status,
-            stderr_output.trim()
+/// Resolves the source for `note` and fetches its raw content, plus the
+/// requested `(field, env var)` pairs as `(env var, value)`.
+/// A `scheme://reference` note (or the mock override) wins over `flag_source`.
+fn fetch_secret_content(
+    note: &str,
+    flag_source: &str,
+    source_config: &source::SourceConfig,
+    fields: &[(String, String)],
+) -> Result<(String, EnvPairs), Box<dyn Error>> {
+    let debug_enabled = source_config.debug;
+
+    // --- Pre-flight Check: Ensure the selected source is usable ---
+    let (source_name, secret_ref) = source::resolve(note, flag_source);
+    let secret_source = source::create(source_name, source_config).and_then(|s| {
+        s.check_available()?;
+        Ok(s)
+    })?;
+
+    debug_eprintln(
+        debug_enabled,
+        format_args!(
+            "Fetching secret content for note: '{}' (source: {})",
+            secret_ref,
+            secret_source.name()
+        ),
+    );
+    let secret_content = secret_source.fetch(secret_ref).map_err(|e| {
+        let hint = match e {
+            source::SourceError::NotFound(_) => {
+                similar_notes_hint(secret_source.as_ref(), secret_ref, debug_enabled)
+            }
+            _ => String::new(),
+        };
+        format!(
+            "Failed to get note '{}' from {}: {}{}",
+            secret_ref,
+            secret_source.name(),
+            e,
+            hint
         )
-        .into()); // Convert String to Box<dyn Error>
+    })?;
+    debug_eprintln(
+        debug_enabled,
+        format_args!(
+            "Successfully fetched {} bytes of secret content.",
+            secret_content.len()
+        ),
+    );
+
+    let mut field_vars = Vec::with_capacity(fields.len());
+    for (field, env_var) in fields {
+        let value = secret_source.fetch_field(secret_ref, field).map_err(|e| {
+            format!(
+                "Failed to get field '{}' from {} for note '{}': {}",
+                field,
+                secret_source.name(),
+                secret_ref,
+                e
+            )
+        })?;
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Fetched field '{}' as {}.", field, env_var),
+        );
+        field_vars.push((env_var.clone(), value));
+    }
+    Ok((secret_content, field_vars))
+}
+
+/// Returns a "Did you mean ...?" line to follow the error for a note that was
+/// not found, based on the source's listing, or an empty string if nothing
+/// is similar.
+/// Sources that cannot list are silently skipped.
+fn similar_notes_hint(
+    secret_source: &dyn source::SecretSource,
+    note: &str,
+    debug_enabled: bool,
+) -> String {
+    let names = match secret_source.list() {
+        Ok(names) => names,
+        Err(e) => {
+            debug_eprintln(
+                debug_enabled,
+                format_args!("Cannot list notes for suggestions: {}", e),
+            );
+            return String::new();
+        }
+    };
+    let suggestions = suggest::suggestions(note, &names);
+    if suggestions.is_empty() {
+        return String::new();
     }
+    format!(
+        "\n{} Did you mean {}?",
+        RBWCHAIN_PREFIX,
+        suggestions
+            .iter()
+            .map(|s| format!("'{}'", s))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    )
+}
 
-    // Parse the standard output as a UTF-8 string
-    let stdout_str = String::from_utf8(output.stdout)
-        .map_err(|e| format!("Output of '{}' is not valid UTF-8: {}", rbw_cmd_display, e))?;
+/// Lists the notes of `source_name` and lets the user pick one on the
+/// terminal. Fails if the source is unusable or nothing is picked.
+fn pick_note(
+    source_name: &str,
+    source_config: &source::SourceConfig,
+) -> Result<String, Box<dyn Error>> {
+    let source_name = source::resolve_listing(source_name);
+    let names = source::create(source_name, source_config).and_then(|s| {
+        s.check_available()?;
+        s.list()
+    })?;
+    if names.is_empty() {
+        return Err(format!("The {} source has no notes to pick from.", source_name).into());
+    }
 
-    Ok(stdout_str)
+    match picker::pick(&names) {
+        Ok(Some(name)) => Ok(name),
+        Ok(None) => Err("No note picked.".into()),
+        Err(e) => Err(format!("Failed to run the note picker: {}", e).into()),
+    }
 }
 
-/// Parses a string containing lines in "KEY=VALUE" format into a HashMap.
-/// Skips empty lines, comments (#), and lines without '='.
-/// Uses `warn_eprintln` for skippable lines, controlled by the `debug_enabled` flag.
-fn parse_env_vars(
-    content: &str,
-    debug_enabled: bool,
-) -> Result<HashMap<String, String>, Box<dyn Error>> {
-    let mut env_vars = HashMap::new();
-    for line in content.lines() {
-        // Skip empty lines or lines potentially starting with # (comments)
-        let trimmed_line = line.trim();
-        if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
-            continue;
+/// Lists the notes belonging to `group` for `--folder-env`: the members of
+/// the folder named `group` or, if there are none, the notes whose names
+/// start with `group`. Returns the sorted names and whether they came from a
+/// folder. Fails if the source is unusable or nothing matches.
+fn collect_group_notes(
+    source_name: &str,
+    source_config: &source::SourceConfig,
+    group: &str,
+) -> Result<(Vec<String>, bool), Box<dyn Error>> {
+    let source_name = source::resolve_listing(source_name);
+    let secret_source = source::create(source_name, source_config).and_then(|s| {
+        s.check_available()?;
+        Ok(s)
+    })?;
+
+    let folder_names = match secret_source.list_folder(group) {
+        Ok(names) => names,
+        Err(source::SourceError::Unsupported(_)) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let is_folder = !folder_names.is_empty();
+    let mut names = if is_folder {
+        folder_names
+    } else {
+        let mut names = secret_source.list()?;
+        names.retain(|name| name.starts_with(group));
+        names
+    };
+    if names.is_empty() {
+        return Err(format!("No notes found in folder or with prefix '{}'.", group).into());
+    }
+    names.sort();
+    names.dedup();
+    debug_eprintln(
+        source_config.debug,
+        format_args!(
+            "Aggregating {} note(s) from {} '{}': {}",
+            names.len(),
+            if is_folder { "folder" } else { "prefix" },
+            group,
+            names.join(", ")
+        ),
+    );
+    Ok((names, is_folder))
+}
+
+/// Concatenates the raw contents of several notes in order, making sure each
+/// one starts on its own line. A single note is returned unchanged.
+fn concat_contents(contents: &[(NoteSpec, String)]) -> String {
+    let mut combined = String::new();
+    for (_, content) in contents {
+        if !combined.is_empty() && !combined.ends_with('\n') {
+            combined.push('\n');
         }
+        combined.push_str(content);
+    }
+    combined
+}
 
-        // Split the line at the first '='
-        if let Some((key, value)) = trimmed_line.split_once('=') {
-            // Basic trimming, consider more robust parsing if needed (e.g., handling quotes)
-            let key = key.trim();
-            let value = value.trim();
-            if !key.is_empty() {
-                // Ensure the key is not empty after trimming
-                env_vars.insert(key.to_string(), value.to_string());
-            } else {
-                // Use the conditional warning printer
-                warn_eprintln(
-                    debug_enabled,
-                    format_args!("Skipping line with empty key: '{}'", line),
+/// The lowercase hex SHA-256 of `content`.
+fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Marks a parsed value to be base64-decoded before injection.
+const BASE64_DIRECTIVE: &str = "base64:";
+
+/// Replaces values carrying the `base64:` directive, and the whole values of
+/// the variables named in `keys`, with their base64-decoded bytes.
+fn decode_base64_values(
+    vars: &mut HashMap<OsString, OsString>,
+    keys: &[String],
+) -> Result<(), String> {
+    for (key, value) in vars.iter_mut() {
+        let forced = keys.iter().any(|k| OsStr::new(k) == key);
+        let encoded = match value.to_str() {
+            Some(v) if forced => v,
+            Some(v) => match v.strip_prefix(BASE64_DIRECTIVE) {
+                Some(encoded) => encoded,
+                None => continue,
+            },
+            None => continue,
+        };
+        let decoded = BASE64_STANDARD.decode(encoded.trim()).map_err(|e| {
+            format!(
+                "Value of '{}' is not valid base64: {}",
+                key.to_string_lossy(),
+                e
+            )
+        })?;
+        if decoded.contains(&0) {
+            return Err(format!(
+                "Decoded value of '{}' contains a NUL byte and cannot be passed in the environment; use file mode (-f) instead.",
+                key.to_string_lossy()
+            ));
+        }
+        *value = OsString::from_vec(decoded);
+    }
+    Ok(())
+}
+
+/// Reads and parses the local dotenv files of `--env-file` in order, later
+/// files overriding earlier ones.
+fn read_env_files(
+    paths: &[PathBuf],
+    options: &parse::ParseOptions,
+) -> Result<HashMap<String, String>, String> {
+    let options = parse::ParseOptions {
+        format: parse::Format::Env,
+        ..options.clone()
+    };
+    let mut vars = HashMap::new();
+    for path in paths {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read env file '{}': {}", path.display(), e))?;
+        let parsed = parse::parse_env_vars(&content, &options)
+            .map_err(|e| format!("Failed to parse env file '{}': {}", path.display(), e))?;
+        vars.extend(parsed);
+    }
+    Ok(vars)
+}
+
+/// Parses the contents of several notes into one set of variables, later
+/// notes overriding earlier ones and labelled notes prefixing their keys.
+fn note_vars(
+    contents: &[(NoteSpec, String)],
+    options: &parse::ParseOptions,
+) -> Result<HashMap<String, String>, String> {
+    let mut vars = HashMap::new();
+    for (note, content) in contents {
+        let parsed = parse::parse_env_vars(content, options)
+            .map_err(|e| format!("Failed to parse secret note '{}': {}", note.name, e))?;
+        let prefix = note.key_prefix.as_deref().unwrap_or_default();
+        vars.extend(
+            parsed
+                .into_iter()
+                .map(|(key, value)| (format!("{}{}", prefix, key), value)),
+        );
+    }
+    Ok(vars)
+}
+
+/// Reads the template at `path` and fills it with the variables parsed from
+/// the notes.
+fn render_template(
+    path: &Path,
+    contents: &[(NoteSpec, String)],
+    options: &parse::ParseOptions,
+) -> Result<String, String> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read template '{}': {}", path.display(), e))?;
+    let vars = note_vars(contents, options)?;
+    template::render(&template, &vars)
+        .map_err(|e| format!("Failed to render template '{}': {}", path.display(), e))
+}
+
+/// Fetches the raw bytes of the attachment `name` of `note`.
+fn fetch_attachment(
+    note: &str,
+    flag_source: &str,
+    source_config: &source::SourceConfig,
+    name: &str,
+) -> Result<Vec<u8>, String> {
+    let (source_name, secret_ref) = source::resolve(note, flag_source);
+    let secret_source = source::create(source_name, source_config).map_err(|e| e.to_string())?;
+    let content = secret_source
+        .fetch_attachment(secret_ref, name)
+        .map_err(|e| {
+            format!(
+                "Failed to fetch attachment '{}' of '{}': {}",
+                name, secret_ref, e
+            )
+        })?;
+    debug_eprintln(
+        source_config.debug,
+        format_args!("Fetched attachment '{}' ({} bytes).", name, content.len()),
+    );
+    Ok(content)
+}
+
+/// Syncs the sources of `notes` with their servers (--sync), or only those
+/// that last synced more than `max_age` ago (--sync-if-older-than). A sync
+/// that fails is a warning, as the local copy can still be used.
+fn sync_sources(
+    notes: &[&str],
+    flag_source: &str,
+    source_config: &source::SourceConfig,
+    max_age: Option<Duration>,
+) {
+    let mut synced: Vec<&str> = Vec::new();
+    for note in notes {
+        let (source_name, _) = source::resolve(note, flag_source);
+        if synced.contains(&source_name) {
+            continue;
+        }
+        synced.push(source_name);
+        let Ok(secret_source) = source::create(source_name, source_config) else {
+            continue; // Reported when fetching
+        };
+        let last_sync = secret_source.last_sync();
+        if let (Some(max_age), Some(last_sync)) = (max_age, last_sync) {
+            let age = last_sync.elapsed().unwrap_or_default();
+            if age <= max_age {
+                debug_eprintln(
+                    source_config.debug,
+                    format_args!(
+                        "The {} source synced {:?} ago; not syncing.",
+                        source_name, age
+                    ),
                 );
+                continue;
             }
-        } else {
-            // Use the conditional warning printer
-            warn_eprintln(
-                debug_enabled,
-                format_args!("Skipping invalid line in env file content: '{}'", line),
+        }
+        debug_eprintln(
+            source_config.debug,
+            format_args!("Syncing the {} source.", source_name),
+        );
+        if let Err(e) = secret_source.sync() {
+            eprintln!(
+                "{} Warning: Failed to sync the {} source, using its local copy: {}",
+                RBWCHAIN_PREFIX, source_name, e
             );
         }
     }
-    Ok(env_vars)
+}
+
+/// Fetches the raw contents of `notes` again for --watch, the attachment
+/// `name` of each if given, syncing each source first with `sync`. Unlike
+/// the first fetch, failures are returned rather than fatal.
+fn refetch_contents(
+    notes: &[String],
+    flag_source: &str,
+    source_config: &source::SourceConfig,
+    attachment: Option<&str>,
+    sync: bool,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut synced: Vec<&str> = Vec::new();
+    let mut contents = Vec::with_capacity(notes.len());
+    for note in notes {
+        let (source_name, secret_ref) = source::resolve(note, flag_source);
+        let secret_source =
+            source::create(source_name, source_config).map_err(|e| e.to_string())?;
+        if sync && !synced.contains(&source_name) {
+            secret_source.sync().map_err(|e| e.to_string())?;
+            synced.push(source_name);
+        }
+        let content = match attachment {
+            Some(name) => secret_source.fetch_attachment(secret_ref, name),
+            None => secret_source.fetch(secret_ref).map(String::into_bytes),
+        };
+        contents.push(content.map_err(|e| e.to_string())?);
+    }
+    Ok(contents)
+}
+
+/// Builds the rewrite of the -f files in `targets` (--reload-signal,
+/// --refresh-file) from new raw contents of `notes` and then of the specs'
+/// own notes, rendered like the first ones: a spec's own note, else the
+/// template or converted notes, else the notes concatenated.
+fn file_rewriter(
+    cli: &Cli,
+    notes: &[NoteSpec],
+    targets: Vec<(Option<String>, refresh::Target)>,
+    parse_options: &parse::ParseOptions,
+    file_owner: Option<u32>,
+) -> watch::Rewrite {
+    let notes = notes.to_vec();
+    let (template, file_format) = (cli.template.clone(), cli.file_format);
+    // Attachments are written as they are, never converted
+    let binary = cli.attachment.is_some();
+    let parse_options = parse_options.clone();
+    let ownership = refresh::Ownership {
+        mode: cli.file_mode,
+        uid: file_owner,
+        gid: cli.file_group,
+    };
+    Box::new(move |raw_contents: &[Vec<u8>]| {
+        let (raw_notes, raw_own) = raw_contents.split_at(notes.len());
+        let contents: Vec<(NoteSpec, String)> = notes
+            .iter()
+            .cloned()
+            .zip(
+                raw_notes
+                    .iter()
+                    .map(|raw| String::from_utf8_lossy(raw).into_owned()),
+            )
+            .collect();
+        let generated = match (&template, file_format) {
+            (Some(path), _) => Some(render_template(path, &contents, &parse_options)?),
+            (None, Some(format)) => Some(convert::render(
+                note_vars(&contents, &parse_options)?,
+                format,
+            )?),
+            (None, None) => None,
+        };
+        let mut raw_own = raw_own.iter();
+        for (own_note, target) in &targets {
+            let content = match (own_note, &generated) {
+                (Some(note), _) => {
+                    let own = raw_own.next().cloned().unwrap_or_default();
+                    match file_format {
+                        Some(format) if !binary => {
+                            let own_spec = NoteSpec {
+                                name: note.clone(),
+                                key_prefix: None,
+                            };
+                            let text = String::from_utf8_lossy(&own).into_owned();
+                            let vars = note_vars(&[(own_spec, text)], &parse_options)?;
+                            convert::render(vars, format)?.into_bytes()
+                        }
+                        _ => own,
+                    }
+                }
+                (None, Some(generated)) => generated.clone().into_bytes(),
+                (None, None) if binary => raw_notes.concat(),
+                (None, None) => concat_contents(&contents).into_bytes(),
+            };
+            refresh::rewrite(target, &content, ownership)
+                .map_err(|e| format!("Failed to rewrite an -f file: {}", e))?;
+        }
+        Ok(())
+    })
+}
+
+/// Fetches every attachment of `note` as a file name and its content.
+fn fetch_attachments(
+    note: &str,
+    flag_source: &str,
+    source_config: &source::SourceConfig,
+) -> Result<Vec<(OsString, OsString)>, String> {
+    let (source_name, secret_ref) = source::resolve(note, flag_source);
+    let secret_source = source::create(source_name, source_config).map_err(|e| e.to_string())?;
+    let names = secret_source
+        .list_attachments(secret_ref)
+        .map_err(|e| format!("Failed to list the attachments of '{}': {}", secret_ref, e))?;
+    let mut attachments = Vec::with_capacity(names.len());
+    for name in names {
+        if !is_file_name(&name) {
+            return Err(format!(
+                "Attachment '{}' of '{}' is not a valid file name.",
+                name, secret_ref
+            ));
+        }
+        let content = secret_source
+            .fetch_attachment(secret_ref, &name)
+            .map_err(|e| format!("Failed to fetch attachment '{}': {}", name, e))?;
+        debug_eprintln(
+            source_config.debug,
+            format_args!("Fetched attachment '{}' ({} bytes).", name, content.len()),
+        );
+        attachments.push((OsString::from(name), OsString::from_vec(content)));
+    }
+    Ok(attachments)
+}
+
+/// Splits the names in `vars` into those that `parent_env` already sets and
+/// new ones, both sorted. rbwchain's own variables are left out.
+fn collisions(
+    vars: &HashMap<OsString, OsString>,
+    parent_env: &HashMap<OsString, OsString>,
+) -> (Vec<String>, Vec<String>) {
+    let (mut existing, mut new): (Vec<String>, Vec<String>) = vars
+        .keys()
+        .map(|key| key.to_string_lossy().into_owned())
+        .filter(|key| !key.starts_with("RBWCHAIN_"))
+        .partition(|key| parent_env.contains_key(OsStr::new(key)));
+    existing.sort();
+    new.sort();
+    (existing, new)
+}
+
+/// Values shorter than this are not looked for in the command line, as they
+/// are likely to occur there by chance (`1`, `true`, ...).
+const MIN_LEAK_LENGTH: usize = 6;
+
+/// Returns the sorted names of the `secrets` whose value occurs in one of
+/// the `args`.
+fn leaked_secrets(args: &[OsString], secrets: &[(String, OsString)]) -> Vec<String> {
+    let mut leaked: Vec<String> = secrets
+        .iter()
+        .filter(|(_, value)| value.len() >= MIN_LEAK_LENGTH)
+        .filter(|(_, value)| {
+            let value = value.as_bytes();
+            args.iter()
+                .any(|arg| arg.as_bytes().windows(value.len()).any(|w| w == value))
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    leaked.sort();
+    leaked.dedup();
+    leaked
+}
+
+/// Returns the names in `required` that are absent from `vars` or empty.
+fn missing_required<'a>(
+    required: &'a [String],
+    vars: &HashMap<OsString, OsString>,
+) -> Vec<&'a str> {
+    required
+        .iter()
+        .filter(|name| {
+            vars.get(OsStr::new(name.as_str()))
+                .is_none_or(|v| v.is_empty())
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+/// Which value a variable gets when it is both exported by the parent
+/// process and fetched by rbwchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Precedence {
+    /// The parent's value is kept.
+    Parent,
+    /// The fetched value replaces the parent's.
+    Secret,
+}
+
+/// Temporary files and directories that must exist while the command runs.
+/// Dropping the guard wipes and deletes them.
+#[derive(Default)]
+struct TempGuard {
+    files: Vec<NamedTempFile>,
+    dirs: Vec<TempDir>,
+    /// Files written to a caller-chosen path.
+    targets: Vec<TempPath>,
+    /// Descriptors the command inherits (`--memfd`, `--fd`), with the path
+    /// it opens them by.
+    descriptors: Vec<(OwnedFd, String)>,
+}
+
+impl Drop for TempGuard {
+    /// Wipes the files before they are deleted. This is best effort: a file
+    /// the command already removed cannot be wiped. They are wiped by path,
+    /// as --reload-signal or --refresh-file may have replaced them.
+    fn drop(&mut self) {
+        for file in &self.files {
+            let _ = perms::wipe_path(file.path());
+        }
+        for target in &self.targets {
+            let _ = perms::wipe_path(target);
+        }
+        for dir in &self.dirs {
+            let Ok(entries) = fs::read_dir(dir.path()) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let _ = perms::wipe_path(&entry.path());
+            }
+        }
+    }
+}
+
+impl TempGuard {
+    fn paths(&self) -> Vec<&OsStr> {
+        let files = self.files.iter().map(|f| f.path().as_os_str());
+        files
+            .chain(self.dirs.iter().map(|d| d.path().as_os_str()))
+            .chain(self.targets.iter().map(|t| t.as_os_str()))
+            .collect()
+    }
+
+    /// Hands the files to a detached process that wipes and deletes them
+    /// once this process has exited, for `--exec`, where nothing is left to
+    /// clean up after the command. This process keeps its descriptors open.
+    fn detach(self) -> std::io::Result<()> {
+        let parent = std::process::id();
+        // SAFETY: No other thread runs at this point, so the child may
+        // allocate and run destructors.
+        match unsafe { libc::fork() } {
+            -1 => Err(std::io::Error::last_os_error()),
+            0 => {
+                // SAFETY: Plain system calls on descriptors owned by nothing
+                // else. A new session keeps Ctrl-C away, and /dev/null keeps
+                // pipes on stdout from waiting for this process.
+                unsafe {
+                    libc::setsid();
+                    let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+                    if null >= 0 {
+                        for fd in 0..=2 {
+                            libc::dup2(null, fd);
+                        }
+                    }
+                }
+                // Once the command exits, this process is handed to another parent
+                while std::os::unix::process::parent_id() == parent {
+                    thread::sleep(Duration::from_millis(200));
+                }
+                drop(self);
+                // SAFETY: Exits without running the parent's cleanup again.
+                unsafe { libc::_exit(0) }
+            }
+            _ => {
+                std::mem::forget(self);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The directory for temporary files: `--tmpdir`, else `$XDG_RUNTIME_DIR`
+/// (a per-user tmpfs on most systems) if it exists, else the system default.
+/// `$XDG_RUNTIME_DIR` is private to us, so it is skipped if the command runs
+/// as `other_user`.
+fn temp_location(tmpdir: Option<&Path>, other_user: bool) -> PathBuf {
+    if let Some(dir) = tmpdir {
+        // Absolute, as the command may run in another directory (--chdir)
+        return std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    }
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty() && !other_user)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Writes `content` to `path` (`--file-path`), which must not exist yet and
+/// must be in a directory that only its owner (us or root) can modify. The
+/// file gets `mode`, owner `uid` and group `gid` and is deleted when the
+/// returned guard drops.
+fn write_target_file(
+    path: &Path,
+    content: &[u8],
+    mode: u32,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> Result<TempPath, String> {
+    // Absolute, as the command may run in another directory (--chdir)
+    let path = &std::path::absolute(path)
+        .map_err(|e| format!("Cannot use '{}': {}", path.display(), e))?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let metadata = fs::metadata(directory)
+        .map_err(|e| format!("Cannot use directory '{}': {}", directory.display(), e))?;
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let owned = metadata.uid() == unsafe { libc::geteuid() } || metadata.uid() == 0;
+    let directory_mode = metadata.permissions().mode();
+    let shared = directory_mode & 0o022 != 0 && directory_mode & 0o1000 == 0;
+    if !owned || shared {
+        return Err(format!(
+            "Refusing to write to '{}': its directory can be modified by other users.",
+            path.display()
+        ));
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => {
+                format!("Refusing to overwrite existing file '{}'.", path.display())
+            }
+            _ => format!("Failed to create '{}': {}", path.display(), e),
+        })?;
+    // From here on, the file is removed again on any failure
+    let guard = TempPath::from_path(path);
+    file.write_all(content)
+        .and_then(|()| file.flush())
+        .and_then(|()| perms::apply(&file, mode, uid, gid))
+        .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))?;
+    Ok(guard)
+}
+
+/// Whether `name` can be used as the name of a file inside a directory,
+/// without pointing outside of it.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\0'])
+}
+
+/// Writes each of `vars` to its own file, named after the variable, in a new
+/// private temporary directory under `location` (`--files-dir`). The
+/// directory and files belong to `owner` if given.
+fn write_files_dir(
+    vars: &HashMap<OsString, OsString>,
+    location: &Path,
+    owner: Option<u32>,
+) -> Result<TempDir, String> {
+    let dir = Builder::new()
+        .permissions(Permissions::from_mode(0o700))
+        .tempdir_in(location)
+        .map_err(|e| format!("Failed to create temporary directory: {}", e))?;
+    if owner.is_some() {
+        std::os::unix::fs::chown(dir.path(), owner, None)
+            .map_err(|e| format!("Failed to hand over {}: {}", dir.path().display(), e))?;
+    }
+    for (key, value) in vars {
+        let path = dir.path().join(key);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .and_then(|mut file| {
+                file.write_all(value.as_bytes())?;
+                perms::apply(&file, 0o600, owner, None)
+            })
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(dir)
+}
+
+// --- Note Selection ---
+
+/// A note to fetch, optionally with a prefix for its parsed keys.
+#[derive(Debug, Clone)]
+struct NoteSpec {
+    /// The note (or `scheme://` reference) handed to the source.
+    name: String,
+    /// Prepended to every parsed key, e.g. `DB_` for `db=postgres-creds`.
+    key_prefix: Option<String>,
+}
+
+impl NoteSpec {
+    /// Parses `[LABEL=]NOTE`. The label must be a valid identifier
+    /// (`[A-Za-z_][A-Za-z0-9_]*`), so notes that merely contain `=` (such as
+    /// `cmd://` references) are taken verbatim.
+    fn parse(spec: &str) -> NoteSpec {
+        if let Some((label, name)) = spec.split_once('=') {
+            let is_identifier = label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if is_identifier && !name.is_empty() {
+                return NoteSpec {
+                    name: name.to_string(),
+                    key_prefix: Some(format!("{}_", label.to_uppercase())),
+                };
+            }
+        }
+        NoteSpec {
+            name: spec.to_string(),
+            key_prefix: None,
+        }
+    }
+}
+
+/// A `-f` file to write, and the variable pointing to it.
+#[derive(Debug, Clone)]
+struct FileSpec {
+    /// The variable set to the file's path.
+    var: String,
+    /// Appended to the file name, including the leading dot.
+    suffix: Option<String>,
+    /// A note to write instead of the notes selected otherwise.
+    note: Option<String>,
+}
+
+impl FileSpec {
+    /// Parses `VAR[:EXT][=NOTE]`. `VAR:` requests no extension, so names
+    /// containing dots can be used. Without a ':', the older `VAR.EXT` form
+    /// applies: everything after the last dot is the extension.
+    fn parse(spec: &str) -> Result<FileSpec, String> {
+        let (target, note) = match spec.split_once('=') {
+            Some((target, note)) if !note.is_empty() => (target, Some(note.to_string())),
+            Some(_) => return Err(format!("missing NOTE after '=' in '{}'", spec)),
+            None => (spec, None),
+        };
+        let (var, ext) = match target.split_once(':') {
+            Some((var, ext)) => (var, Some(ext.trim_start_matches('.'))),
+            None => match target.rsplit_once('.') {
+                Some((var, ext)) if !var.is_empty() => (var, Some(ext)),
+                _ => (target, None), // No dot, or starts with dot: whole string is name
+            },
+        };
+        if var.is_empty() {
+            return Err(format!(
+                "the environment variable name part of '{}' cannot be empty",
+                spec
+            ));
+        }
+        Ok(FileSpec {
+            var: var.to_string(),
+            suffix: ext.filter(|e| !e.is_empty()).map(|e| format!(".{}", e)),
+            note,
+        })
+    }
+}
+
+/// The shape of generated temp file names (`--file-name-template`).
+#[derive(Debug, Clone)]
+struct NameTemplate {
+    prefix: String,
+    /// The number of random characters.
+    random: usize,
+    /// The rest of the name; `{ext}` stands for the `-f` extension.
+    suffix: String,
+}
+
+impl NameTemplate {
+    /// Parses a template like `app-XXXX.{ext}`, where the last run of `X`
+    /// is replaced by random characters. Without any `X`, the name is fixed.
+    fn parse(template: &str) -> Result<NameTemplate, String> {
+        if template.is_empty() || template.contains('/') {
+            return Err(format!("'{}' must be a file name", template));
+        }
+        let end = template.rfind('X').map_or(0, |i| i + 1);
+        let start = template[..end].trim_end_matches('X').len();
+        Ok(NameTemplate {
+            prefix: template[..start].to_string(),
+            random: end - start,
+            suffix: template[end..].to_string(),
+        })
+    }
+
+    /// The part after the random characters, for a file with the extension
+    /// `suffix` (including its dot). Without one, `.{ext}` is left out.
+    fn suffix(&self, suffix: Option<&str>) -> String {
+        match suffix {
+            Some(suffix) => self.suffix.replace("{ext}", &suffix[1..]),
+            None => self.suffix.replace(".{ext}", "").replace("{ext}", ""),
+        }
+    }
+}
+
+/// Parses a `--field NAME[=ENV_VAR]` spec. Without an explicit variable,
+/// the field name is mapped to an uppercased environment variable name.
+fn parse_field_spec(spec: &str) -> Result<(String, String), String> {
+    let (field, env_var) = match spec.split_once('=') {
+        Some((field, env_var)) => (field.to_string(), env_var.to_string()),
+        None => (spec.to_string(), source::env_var_name(spec).to_uppercase()),
+    };
+    if field.is_empty() || env_var.is_empty() {
+        return Err(format!("expected NAME or NAME=ENV_VAR, got '{}'", spec));
+    }
+    Ok((field, env_var))
+}
+
+/// Parses a `-e KEY=VALUE` literal variable.
+fn parse_literal_var(spec: &str) -> Result<(String, String), String> {
+    let (key, value) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", spec))?;
+    if !parse::is_env_name(key) {
+        return Err(format!(
+            "'{}' is not a valid environment variable name",
+            key
+        ));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parses the name of a variable to set, such as `--as VAR`.
+fn parse_var_name(name: &str) -> Result<String, String> {
+    if !parse::is_env_name(name) {
+        return Err(format!(
+            "'{}' is not a valid environment variable name",
+            name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Rewrites a leading subcommand into the flags it stands for, before clap
+/// sees the arguments: `rbwchain up NOTE` runs `./Procfile` like
+/// `--procfile Procfile`, and `rbwchain serve NOTE COMMAND` is
+/// `--serve`. A `--supervised` inserted by a supervising
+/// rbwchain is skipped, as it goes before the user's arguments.
+fn expand_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let at = match args.get(1) {
+        Some(arg) if arg == "--supervised" => 2,
+        _ => 1,
+    };
+    if args.get(at).is_some_and(|arg| arg == "up") {
+        let has_procfile = args
+            .iter()
+            .any(|arg| arg == "--procfile" || arg.as_bytes().starts_with(b"--procfile="));
+        if has_procfile {
+            args.remove(at);
+        } else {
+            args.splice(at..=at, ["--procfile".into(), "Procfile".into()]);
+        }
+    } else if args.get(at).is_some_and(|arg| arg == "serve") {
+        args[at] = OsString::from("--serve");
+    }
+    args
 }
 
 // --- Command Line Argument Parsing ---
@@ -134,129 +993,1331 @@ By default, it parses the secrets as KEY=VALUE pairs and sets them as environmen
 If -f/--file is used with ENV_VAR_NAME[.EXT], it writes the raw secret content to a temporary file with the given suffix \
 (if provided) and sets the ENV_VAR_NAME environment variable to its path. \
 Error messages are always printed to stderr. Use --debug for verbose output.\n\n\
-Arguments after SECRET_NOTE (including flags like --help) are passed directly to the COMMAND.",
+Arguments after SECRET_NOTE (including flags like --help) are passed directly to the COMMAND.\n\n\
+`rbwchain up SECRET_NOTE` runs the processes of ./Procfile, like --procfile Procfile. \
+`rbwchain serve SECRET_NOTE COMMAND` is short for --serve.",
     // Capture all trailing arguments for the child command
     trailing_var_arg = true
 )]
 struct Cli {
-    /// The secret_note to read (using `rbw`)
-    #[arg(required = true, value_name = "SECRET_NOTE")]
+    /// The secret_note to read (using the selected source)
+    #[arg(
+        required_unless_present_any = ["notes", "item_id", "uri", "pick", "folder_env"],
+        default_value = "",
+        hide_default_value = true,
+        value_name = "SECRET_NOTE"
+    )]
     secret_note: String,
 
+    /// The secret source backend to fetch SECRET_NOTE from.
+    #[arg(long = "source", value_name = "SOURCE", default_value = source::DEFAULT_SOURCE)]
+    source: String,
+
+    /// Path to the rbw binary to execute instead of `rbw` from PATH.
+    #[arg(long = "rbw-path", value_name = "PATH", env = "RBWCHAIN_RBW_PATH")]
+    rbw_path: Option<PathBuf>,
+
+    /// The rbw profile to use, passed to rbw as RBW_PROFILE.
+    #[arg(
+        long = "rbw-profile",
+        value_name = "PROFILE",
+        env = "RBWCHAIN_RBW_PROFILE"
+    )]
+    rbw_profile: Option<String>,
+
+    /// Sync the source with its server (`rbw sync`) before fetching, so the
+    /// secrets reflect edits made elsewhere.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    sync: bool,
+
+    /// Like --sync, but only if the source last synced longer ago than this,
+    /// e.g. 1h, to skip the round trip most of the time.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, conflicts_with = "sync")]
+    sync_if_older_than: Option<Duration>,
+
+    /// Bitwarden Secrets Manager project id to read secrets from (`--source bws`).
+    /// SECRET_NOTE then selects a secret by name, or `*` for all of them.
+    #[arg(long = "project", value_name = "ID")]
+    project: Option<String>,
+
+    /// A note to read, as an alternative to SECRET_NOTE. Can be repeated; the
+    /// parsed variables are merged, later notes overriding earlier ones.
+    /// `LABEL=NOTE` prefixes the note's keys with `LABEL_` (uppercased).
+    /// When given, the first positional argument is treated as the COMMAND.
+    #[arg(
+        short = 'n',
+        long = "note",
+        visible_alias = "entry",
+        value_name = "[LABEL=]NOTE"
+    )]
+    notes: Vec<String>,
+
+    /// A note whose variables are applied on top of the other notes', e.g.
+    /// `rbwchain app-base --overlay app-prod -- cmd`. Can be repeated, later
+    /// overlays winning.
+    #[arg(long, value_name = "NOTE")]
+    overlay: Vec<String>,
+
+    /// Pick the note interactively from the source's listing with a fuzzy
+    /// finder on the terminal. The first positional argument is then treated
+    /// as the COMMAND.
+    #[arg(long = "pick", conflicts_with_all = ["notes", "item_id", "uri"])]
+    pick: bool,
+
+    /// Fetch every note in this folder (or, if the folder is empty, every
+    /// note whose name starts with this prefix) and merge them in name order.
+    /// The first positional argument is then treated as the COMMAND.
+    #[arg(
+        long = "folder-env",
+        value_name = "FOLDER_OR_PREFIX",
+        conflicts_with_all = ["notes", "pick", "item_id", "uri", "folder"]
+    )]
+    folder_env: Option<String>,
+
+    /// Select the Bitwarden item by its id instead of SECRET_NOTE (`--source rbw`),
+    /// so renamed or duplicated items still resolve. When given, the first
+    /// positional argument is treated as the COMMAND.
+    #[arg(long = "id", value_name = "UUID", conflicts_with = "notes")]
+    item_id: Option<String>,
+
+    /// Select the Bitwarden item whose URI matches this URL instead of
+    /// SECRET_NOTE (`--source rbw`). When given, the first positional argument
+    /// is treated as the COMMAND.
+    #[arg(long = "uri", value_name = "URL", conflicts_with_all = ["notes", "item_id"])]
+    uri: Option<String>,
+
+    /// Login item mode (`--source rbw`): inject the item's username and password
+    /// as PREFIX_USERNAME and PREFIX_PASSWORD. PREFIX defaults to the note name,
+    /// uppercased, and must be given with `--id` or `--uri`; `--login=` injects
+    /// plain USERNAME/PASSWORD.
+    #[arg(long = "login", value_name = "PREFIX", num_args = 0..=1, require_equals = true)]
+    login: Option<Option<String>>,
+
+    /// In login mode, also inject the current TOTP code as PREFIX_TOTP (via `rbw code`).
+    #[arg(long = "totp", requires = "login")]
+    totp: bool,
+
+    /// Inject a custom field of the item as ENV_VAR (`--source rbw`, via
+    /// `rbw get --field`). Can be repeated. ENV_VAR defaults to the field
+    /// name, uppercased.
+    #[arg(long = "field", value_name = "NAME[=ENV_VAR]", value_parser = parse_field_spec)]
+    fields: Vec<(String, String)>,
+
+    /// Only consider items in this Bitwarden folder (`--source rbw`), for
+    /// names shared across folders.
+    #[arg(long = "folder", value_name = "NAME")]
+    folder: Option<String>,
+
+    /// Path to the KeePassXC database (`--source keepassxc`).
+    #[arg(long = "db", value_name = "PATH")]
+    database: Option<PathBuf>,
+
+    /// Fetch this attachment of the entry instead of its content (`--source
+    /// keepassxc`, or rbw through Bitwarden's `bw` CLI).
+    #[arg(long = "attachment", value_name = "NAME")]
+    attachment: Option<String>,
+
+    /// Print which fetched variables override exported ones and which are
+    /// new (always done with --debug). Values are never printed.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    report_collisions: bool,
+
+    /// Refuse to run the command if a fetched variable would override one
+    /// already exported by the parent process. `-e` variables are exempt.
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "precedence")]
+    no_override: bool,
+
+    /// Only pass the parent's variables matching one of these names or globs
+    /// (e.g. `PATH,HOME,LC_*`) on to the command, instead of its whole
+    /// environment. Comma-separated and/or repeatable.
+    #[arg(long, value_name = "VAR", value_delimiter = ',')]
+    inherit: Vec<String>,
+
+    /// Whether variables already exported by the parent process win over
+    /// fetched ones (`parent`) or are overridden by them (`secret`).
+    /// `-e` variables are always set.
+    #[arg(long, value_enum, value_name = "WINNER", default_value_t = Precedence::Secret)]
+    precedence: Precedence,
+
+    /// Set an additional, non-secret variable for the command, e.g.
+    /// `-e RUST_LOG=debug`. Can be repeated; overrides fetched variables.
+    #[arg(short = 'e', long = "env", value_name = "KEY=VALUE", value_parser = parse_literal_var)]
+    literal_vars: Vec<(String, String)>,
+
+    /// Read non-secret variables from a local dotenv file, e.g.
+    /// `--env-file ./local.env`. Can be repeated, later files overriding
+    /// earlier ones. Variables from the notes win unless `--env-file-override`.
+    #[arg(long = "env-file", value_name = "PATH")]
+    env_files: Vec<PathBuf>,
+
+    /// Let `--env-file` variables override those from the notes.
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "env_files")]
+    env_file_override: bool,
+
     /// Provide secrets via a temporary file path set in an environment variable.
     /// Writes the raw secret content to a temp file and sets ENV_VAR_NAME=</path/to/tempfile>
-    /// for the child command. The value can be `ENV_VAR_NAME` or `ENV_VAR_NAME.EXT`.
-    /// If `.EXT` is provided, the temporary file will have that extension.
-    #[arg(short = 'f', long = "file", value_name = "ENV_VAR_NAME[.EXT]")]
-    file_env_var: Option<String>,
+    /// for the child command. The value can be `ENV_VAR_NAME` or `ENV_VAR_NAME:EXT`
+    /// (or the older `ENV_VAR_NAME.EXT`).
+    /// If `EXT` is provided, the temporary file will have that extension.
+    /// Can be repeated; `ENV_VAR_NAME[:EXT]=NOTE` writes NOTE instead of the
+    /// notes selected otherwise.
+    #[arg(short = 'f', long = "file", value_name = "ENV_VAR_NAME[:EXT][=NOTE]", value_parser = FileSpec::parse)]
+    file_env_vars: Vec<FileSpec>,
+
+    /// Do not delete the -f files after the command finishes, and print their
+    /// paths. For debugging only: the secret stays on disk.
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "file_env_vars")]
+    keep_file: bool,
+
+    /// Name the -f temp files after this template, e.g. `app-XXXX.{ext}`:
+    /// the last run of X becomes random characters, `{ext}` the extension.
+    /// A template without X is used as-is and must not exist yet.
+    #[arg(long, value_name = "TEMPLATE", value_parser = NameTemplate::parse)]
+    file_name_template: Option<NameTemplate>,
+
+    /// Permissions of the -f files, in octal. Widen them (e.g. 0640, with
+    /// --file-group) only if another user must read the secret.
+    #[arg(long, value_name = "MODE", default_value = "0600", value_parser = perms::parse_mode)]
+    file_mode: u32,
+
+    /// Hand the -f files to this group (name or id), e.g. for a helper
+    /// group or a command that drops privileges.
+    #[arg(long, value_name = "GROUP", value_parser = perms::parse_group)]
+    file_group: Option<u32>,
+
+    /// Refuse to write secret files unless they land on a memory-backed
+    /// filesystem (tmpfs), never on persistent storage.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    require_tmpfs: bool,
+
+    /// Create temporary files in this directory. Defaults to $XDG_RUNTIME_DIR
+    /// if set, else the system's temporary directory.
+    #[arg(long, value_name = "DIR")]
+    tmpdir: Option<PathBuf>,
+
+    /// Linux: put the -f secrets in anonymous in-memory files (memfd) that
+    /// the command inherits, set as `/proc/self/fd/N`. They never exist on
+    /// any filesystem.
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "file_env_vars", conflicts_with_all = ["file_path", "keep_file"])]
+    memfd: bool,
+
+    /// Render this local template into the -f files instead of writing the
+    /// raw note, replacing `{{KEY}}` placeholders with the note's values.
+    #[arg(long, value_name = "PATH", requires = "file_env_vars")]
+    template: Option<PathBuf>,
+
+    /// Write the -f files in this format, converted from the parsed note,
+    /// so one note can feed tools with different config formats.
+    #[arg(
+        long,
+        value_enum,
+        requires = "file_env_vars",
+        conflicts_with = "template"
+    )]
+    file_format: Option<convert::FileFormat>,
+
+    /// Write the -f secret to this path instead of a random temporary file,
+    /// for programs with a hardcoded config location. The file must not
+    /// exist yet and is deleted after the command finishes.
+    #[arg(long, value_name = "PATH", requires = "file_env_vars")]
+    file_path: Option<PathBuf>,
+
+    /// Set VAR to the whole raw note content instead of parsing it, for notes
+    /// holding a single token or blob. A trailing newline is dropped.
+    #[arg(long = "as", value_name = "VAR", value_parser = parse_var_name, conflicts_with = "file_env_vars")]
+    as_var: Option<String>,
+
+    /// Write each parsed variable to its own file, named after the key, in a
+    /// private temporary directory and set ENV_VAR to the directory's path,
+    /// the layout of Docker and Kubernetes secret mounts.
+    #[arg(long, value_name = "ENV_VAR", value_parser = parse_var_name, conflicts_with_all = ["file_env_vars", "as_var"])]
+    files_dir: Option<String>,
+
+    /// Also write the raw note content to the command's standard input, for
+    /// commands that read the secret from stdin (`psql`, `vault login -`).
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    stdin: bool,
+
+    /// Download all attachments of the note's entry into a private temporary
+    /// directory and set ENV_VAR to its path, for binary artifacts such as
+    /// keystores. With the default source they are downloaded with Bitwarden's
+    /// `bw` CLI, which must be unlocked (`BW_SESSION`), as rbw cannot.
+    #[arg(long, value_name = "ENV_VAR", value_parser = parse_var_name)]
+    attachments: Option<String>,
+
+    /// Split a note of concatenated PEM blocks into cert.pem, chain.pem,
+    /// fullchain.pem and key.pem in a private temporary directory, and set
+    /// DIR_VAR to its path.
+    #[arg(long, value_name = "DIR_VAR", value_parser = parse_var_name, conflicts_with_all = ["file_env_vars", "as_var", "files_dir"])]
+    split_pem: Option<String>,
+
+    /// Stop the command if it runs longer than this (e.g. 300s, 5m, 1h):
+    /// its process group receives SIGTERM, then SIGKILL after --kill-after.
+    /// rbwchain then exits with code 124.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// How long a timed-out command may take to exit after SIGTERM before it
+    /// is killed.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, default_value = "10s", requires = "timeout")]
+    kill_after: Duration,
+
+    /// Run the command in this working directory, for tools resolving config
+    /// paths relative to it, without a `sh -c 'cd ...'` layer.
+    #[arg(long, value_name = "DIR")]
+    chdir: Option<PathBuf>,
+
+    /// Run the command with this umask (octal, e.g. 077), so files it creates,
+    /// such as rendered configs holding secrets, are private by default.
+    #[arg(long, value_name = "MASK", value_parser = perms::parse_mode)]
+    umask: Option<u32>,
+
+    /// Pass this as the command's argv[0] instead of the command itself, for
+    /// multi-call binaries like busybox that act on the name they run under.
+    #[arg(long, value_name = "NAME")]
+    argv0: Option<OsString>,
+
+    /// Run the command as this user (name or id), with its groups, when
+    /// rbwchain runs as root. Files written for the command are handed to it.
+    #[arg(long, value_name = "USER", value_parser = perms::parse_user)]
+    user: Option<perms::User>,
+
+    /// Run the command with this primary group (name or id) instead of the
+    /// --user's, when rbwchain runs as root.
+    #[arg(long, value_name = "GROUP", value_parser = perms::parse_group)]
+    group: Option<u32>,
+
+    /// Limit the open file descriptors of the command (setrlimit).
+    #[arg(long, value_name = "N", value_parser = child::parse_limit)]
+    limit_nofile: Option<u64>,
+
+    /// Limit the size of the command's core dumps, e.g. 0 so that a crash
+    /// does not write its memory, secrets included, to disk.
+    #[arg(long, value_name = "SIZE", value_parser = child::parse_limit)]
+    limit_core: Option<u64>,
+
+    /// Limit the address space of the command, e.g. 2G.
+    #[arg(long, value_name = "SIZE", value_parser = child::parse_limit)]
+    limit_as: Option<u64>,
+
+    /// Limit the number of processes of the command's user.
+    #[arg(long, value_name = "N", value_parser = child::parse_limit)]
+    limit_nproc: Option<u64>,
+
+    /// Limit the CPU time of the command, in seconds.
+    #[arg(long, value_name = "SECONDS", value_parser = child::parse_limit)]
+    limit_cpu: Option<u64>,
+
+    /// Run the command at this niceness, from -20 (highest priority, root
+    /// only) to 19 (lowest).
+    #[arg(long, value_name = "N", allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    nice: Option<i32>,
+
+    /// Linux: run the command in this I/O scheduling class, like ionice:
+    /// realtime[:LEVEL], best-effort[:LEVEL] or idle.
+    #[arg(long, value_name = "CLASS[:LEVEL]", value_parser = child::parse_io_priority)]
+    ionice: Option<child::IoPriority>,
+
+    /// Linux: adjust how likely the OOM killer picks the command, from -1000
+    /// (never; root only) to 1000 (first).
+    #[arg(long, value_name = "N", allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-1000..=1000))]
+    oom_score_adj: Option<i32>,
+
+    /// Linux: have the kernel kill the command (PR_SET_PDEATHSIG) if rbwchain
+    /// dies, e.g. killed by a supervisor, so it does not run on orphaned.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    kill_on_parent_exit: bool,
+
+    /// Replace rbwchain with the command (execvp) instead of running it as a
+    /// child, so the command keeps rbwchain's PID and receives signals
+    /// directly. Files written with -f are then deleted by a small detached
+    /// process once the command exits; prefer --memfd, which needs none.
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["stdin", "fd_var", "keep_file", "timeout", "kill_on_parent_exit"])]
+    exec: bool,
+
+    /// Run the command again when it exits: on-failure (exit code other
+    /// than 0, or killed by a signal) or always. Every run fetches the
+    /// secrets anew.
+    #[arg(long, value_enum, value_name = "WHEN", conflicts_with = "exec")]
+    restart: Option<supervise::Restart>,
+
+    /// The delay before running the command again (--restart, --retry-on):
+    /// MIN after a failure, doubling with every further failure in a row up
+    /// to MAX.
+    #[arg(long, value_name = "MIN..MAX", value_parser = supervise::parse_backoff, default_value = "1s..60s")]
+    backoff: supervise::Backoff,
+
+    /// Run the command again when it exits with one of these codes, e.g.
+    /// 75,111 for transient failures, fetching the secrets anew.
+    #[arg(
+        long,
+        value_name = "CODE",
+        value_delimiter = ',',
+        conflicts_with = "exec"
+    )]
+    retry_on: Vec<i32>,
+
+    /// Give up after running the command again this many times (default:
+    /// unlimited with --restart, 3 with --retry-on).
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Set on the runs of a supervising rbwchain (--restart, --retry-on).
+    #[arg(long, hide = true)]
+    supervised: bool,
+
+    /// Fetch the notes again at this interval, e.g. 60s, and when they
+    /// changed, stop the command with SIGTERM and run it again with the new
+    /// secrets.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, conflicts_with_all = ["exec", "init"])]
+    watch: Option<Duration>,
+
+    /// Sync the source with its server (`rbw sync`) before every --watch
+    /// fetch, to see changes made elsewhere.
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "watch")]
+    watch_sync: bool,
+
+    /// On a --watch change, rewrite the -f files and send the command this
+    /// signal (e.g. SIGHUP) instead of restarting it, for daemons that
+    /// reload their credentials on a signal. Variables keep their values.
+    /// With --refresh-file, signal the command after every rewrite.
+    #[arg(long, value_name = "SIGNAL", value_parser = signals::parse_signal, requires = "file_env_vars")]
+    reload_signal: Option<libc::c_int>,
+
+    /// Fetch the notes again at this interval, e.g. 15m, and rewrite the -f
+    /// files in place when they changed, while the command keeps running,
+    /// e.g. for short-lived tokens it reads from the file on every use.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, requires = "file_env_vars", conflicts_with_all = ["exec", "init", "watch"])]
+    refresh_file: Option<Duration>,
+
+    /// Run the commands separated by `:::` at the same time instead of one
+    /// after the other, their output prefixed with their names. rbwchain
+    /// exits like the first one that failed.
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["exec", "timeout"])]
+    parallel: bool,
+
+    /// Run the processes of this Procfile (`name: command` lines) at the
+    /// same time instead of a command, like foreman. Their output is
+    /// prefixed with their names; when one exits, the others are stopped.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["exec", "timeout", "parallel"])]
+    procfile: Option<PathBuf>,
+
+    /// Only run the command once this shell command, run with the same
+    /// environment, succeeds, e.g. `pg_isready -d "$DATABASE_URL"`. It is
+    /// retried per --check-retries and --check-interval. Repeatable.
+    #[arg(long, value_name = "SCRIPT")]
+    check: Vec<String>,
+
+    /// How many times to retry a failing --check before giving up.
+    #[arg(long, value_name = "N", default_value_t = 30)]
+    check_retries: u32,
+
+    /// The delay between --check attempts.
+    #[arg(long, value_name = "DURATION", value_parser = child::parse_duration, default_value = "1s")]
+    check_interval: Duration,
+
+    /// Run this shell command with the same environment before the command,
+    /// e.g. migrations. If it fails, the command does not run. Repeatable.
+    #[arg(long, value_name = "SCRIPT")]
+    pre: Vec<String>,
+
+    /// Run this shell command with the same environment after the command,
+    /// e.g. to revoke a lease, with its exit code in RBWCHAIN_EXIT_CODE.
+    /// Repeatable.
+    #[arg(long, value_name = "SCRIPT", conflicts_with = "exec")]
+    post: Vec<String>,
+
+    /// Act as an init for containers (ENTRYPOINT): reap orphaned zombie
+    /// processes and forward all common signals to the command, like tini.
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "exec")]
+    init: bool,
+
+    /// Pass the raw note content through a pipe the command inherits and set
+    /// ENV_VAR to `/dev/fd/N`, so the secret has no path on any filesystem.
+    #[arg(long = "fd", value_name = "ENV_VAR", value_parser = parse_var_name, conflicts_with_all = ["file_env_vars", "as_var", "files_dir", "split_pem"])]
+    fd_var: Option<String>,
+
+    /// Instead of setting the parsed variables, serve them over a private
+    /// unix socket whose path is set as RBWCHAIN_SOCKET, so the command asks
+    /// for each one when it needs it: `GET KEY` or `LIST`, one per connection.
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["exec", "file_env_vars", "as_var", "files_dir", "split_pem", "fd_var"])]
+    serve: bool,
+
+    /// The syntax of the note content in environment variable mode.
+    #[arg(long, value_enum, default_value_t = parse::Format::Auto)]
+    format: parse::Format,
+
+    /// Do not expand `${NAME}` references in parsed values.
+    #[arg(long = "no-interpolate", action = clap::ArgAction::SetTrue)]
+    no_interpolate: bool,
+
+    /// Expand `$NAME` and `${NAME}` references in values of any format from
+    /// rbwchain's own environment, e.g. `CERT=$HOME/.certs/app.pem`. `$$`
+    /// stands for a literal `$`.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    expand_env: bool,
+
+    /// Keep leading/trailing whitespace of unquoted values: everything after
+    /// the '=' is used verbatim, including any `# comment`.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    preserve_whitespace: bool,
+
+    /// Only inject parsed keys matching one of these globs (`*`, `?`), e.g.
+    /// 'AWS_*'. Comma-separated and/or repeatable.
+    #[arg(long, value_name = "GLOB", value_delimiter = ',')]
+    only: Vec<String>,
+
+    /// Do not inject parsed keys matching any of these globs, e.g. '*_DEV'.
+    /// Comma-separated and/or repeatable.
+    #[arg(long, value_name = "GLOB", value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Inject the parsed key SRC as DST instead. Can be repeated.
+    #[arg(long = "map", value_name = "SRC=DST", value_parser = keys::parse_rename)]
+    renames: Vec<(String, String)>,
+
+    /// Remove PREFIX from parsed keys starting with it, e.g. `--strip-prefix
+    /// PROD_` injects `PROD_DB_URL` as `DB_URL` (overriding a plain `DB_URL`).
+    #[arg(long, value_name = "PREFIX")]
+    strip_prefix: Option<String>,
+
+    /// Prepend PREFIX to every parsed key, e.g. `--prefix MYAPP_`.
+    #[arg(long, value_name = "PREFIX")]
+    prefix: Option<String>,
+
+    /// Uppercase every parsed key (after renaming and prefixing).
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "downcase_keys")]
+    upcase_keys: bool,
+
+    /// Lowercase every parsed key (after renaming and prefixing).
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    downcase_keys: bool,
+
+    /// Convert parsed keys that are not valid environment variable names
+    /// (e.g. `db.password`) by replacing invalid characters with `_`,
+    /// instead of failing.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    sanitize_keys: bool,
+
+    /// Abort on any line that is not a valid KEY=VALUE, comment or blank,
+    /// instead of skipping it, and on any `${NAME}` that is not defined.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    strict: bool,
+
+    /// Only read the keys of this `[SECTION]` of the note (plus those before
+    /// the first section header), without the section prefix.
+    #[arg(long, value_name = "SECTION")]
+    section: Option<String>,
+
+    /// Separator used when flattening nested keys of JSON/YAML/TOML notes
+    /// into variable names.
+    #[arg(long, value_name = "SEP", default_value = "_")]
+    flatten_separator: String,
+
+    /// Flatten at most this many levels of nested objects; deeper objects
+    /// are injected as JSON. 0 disables flattening.
+    #[arg(long, value_name = "N")]
+    flatten_depth: Option<usize>,
+
+    /// Base64-decode the values of these variables before injecting them
+    /// (in addition to values written as `KEY=base64:...`). Comma-separated
+    /// and/or repeatable.
+    #[arg(long, value_name = "KEY", value_delimiter = ',')]
+    decode64: Vec<String>,
+
+    /// Fail before running the command unless all of these variables are
+    /// set and non-empty. Comma-separated and/or repeatable.
+    #[arg(long, value_name = "KEY", value_delimiter = ',')]
+    require: Vec<String>,
+
+    /// Fail before running the command if KEY is set but its value does not
+    /// look like TYPE (url, int, base64 or uuid). Can be repeated.
+    #[arg(long, value_name = "KEY=TYPE", value_parser = validate::parse_rule)]
+    validate: Vec<(String, validate::Kind)>,
+
+    /// Do not set RBWCHAIN_VERSION, RBWCHAIN_SECRET_NOTE and
+    /// RBWCHAIN_SECRET_HASH for the command, so the note name does not show
+    /// up in its environment.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    no_meta: bool,
+
+    /// Refuse to run the command if one of its arguments contains a fetched
+    /// value, instead of only warning about it.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    deny_args_leak: bool,
 
     /// Enable debug logging to stderr.
     #[arg(long, short = 'd', action = clap::ArgAction::SetTrue)]
     debug: bool,
 
-    /// The command and its arguments to execute
-    #[arg(required = true, value_name = "COMMAND_AND_ARGS")]
+    /// The command and its arguments to execute. Several commands separated
+    /// by `:::` run one after the other, stopping at the first failure.
+    /// Without a command, an interactive $SHELL is started.
+    #[arg(allow_hyphen_values = true, value_name = "COMMAND_AND_ARGS")]
     command_and_args: Vec<OsString>,
 }
 
 // --- Main Logic ---
-fn main() -> Result<(), Box<dyn Error>> {
-    // --- Pre-flight Check: Ensure rbw exists ---
-    if which("rbw").is_err() {
-        // Use the dedicated error printer
+fn main() {
+    let code = run().unwrap_or_else(|e| {
+        error_eprintln(format_args!("{}", e));
+        // Stopped before the command ran, as if the signal had ended rbwchain
+        signals::stop_signal().map_or(1, |signal| 128 + signal)
+    });
+    // The only exit, reached once everything the run wrote is cleaned up
+    std::process::exit(code);
+}
+
+/// The notes of a run, how they are fetched and parsed, and their contents.
+struct Secrets {
+    notes: Vec<NoteSpec>,
+    source_config: source::SourceConfig,
+    parse_options: parse::ParseOptions,
+    /// Each note with its content, in order.
+    contents: Vec<(NoteSpec, String)>,
+    /// The `--field` values, as `(env var, value)`.
+    field_vars: EnvPairs,
+    /// The attachment of each note, when -f files get its bytes unchanged.
+    attachment_contents: Vec<Vec<u8>>,
+    /// The raw content of the notes, byte for byte.
+    raw_content: Vec<u8>,
+}
+
+/// What the commands are started with.
+struct Launch {
+    setup: child::Setup,
+    /// The part of our own environment that the command inherits.
+    parent_env: HashMap<OsString, OsString>,
+    /// The variables set for the command.
+    env: HashMap<OsString, OsString>,
+    /// The variables handed out over the socket instead (--serve).
+    served_vars: HashMap<OsString, OsString>,
+    /// The -f files with the note of their spec if it has one, and the raw
+    /// contents of those notes, to fetch and write them again.
+    file_targets: Vec<(Option<String>, refresh::Target)>,
+    own_note_contents: Vec<Vec<u8>>,
+    /// The read end of the --fd pipe, for the first command alone.
+    secret_pipe: Option<RawFd>,
+    /// The names of the Procfile's processes.
+    process_names: Vec<String>,
+}
+
+/// How the commands ended.
+struct Outcome {
+    status: ExitStatus,
+    timed_out: bool,
+    /// The notes changed under --watch, so the run starts over.
+    secret_changed: bool,
+}
+
+/// Runs rbwchain and returns the code to exit with. Whether it succeeds or
+/// fails, the files it wrote are gone by the time it returns.
+fn run() -> Result<i32, Box<dyn Error>> {
+    // 1. Parse Command Line Arguments
+    let mut cli = Cli::parse_from(expand_subcommand(env::args_os().collect()));
+    if (cli.restart.is_some() || !cli.retry_on.is_empty()) && !cli.supervised {
+        // Every attempt is a run of rbwchain of its own
+        let policy = supervise::Policy {
+            restart: cli.restart,
+            retry_on: cli.retry_on.clone(),
+            retries: cli.retries,
+            backoff: cli.backoff,
+        };
+        let status = supervise::run(&policy, cli.init, cli.debug)
+            .map_err(|e| format!("Failed to supervise the command: {}", e))?;
+        return Ok(exit_code(status, cli.debug));
+    }
+    let (notes, source_config) = select_notes(&mut cli)?;
+    let debug_enabled = cli.debug; // Store flag for easy access
+
+    let parse_options = parse::ParseOptions {
+        debug: debug_enabled,
+        format: cli.format,
+        interpolate: !cli.no_interpolate,
+        expand_env: cli.expand_env,
+        strict: cli.strict,
+        preserve_whitespace: cli.preserve_whitespace,
+        section: cli.section.clone(),
+        flatten_separator: cli.flatten_separator.clone(),
+        flatten_depth: cli.flatten_depth,
+    };
+    // Local env files are read first, so a typo fails before any unlock prompt
+    let env_file_vars = read_env_files(&cli.env_files, &parse_options)?;
+    // So is the Procfile, whose processes become `sh -c` commands
+    let process_names = read_procfile(&mut cli)?;
+
+    // 2. Fetch Secret Content (always needed), once per note in order
+    let secrets = fetch_notes(&cli, notes, source_config, parse_options)?;
+
+    // 3. Set up the Command
+    let interactive = prepare_commands(&mut cli)?;
+    let mut launch = Launch {
+        setup: child_setup(&cli)?,
+        parent_env: parent_env(&cli),
+        env: wrapper_vars(&cli, &secrets, interactive),
+        served_vars: HashMap::new(),
+        file_targets: Vec::new(),
+        own_note_contents: Vec::new(),
+        secret_pipe: None,
+        process_names,
+    };
+
+    // Keep temp files alive until command finishes if using file mode
+    // `NamedTempFile` automatically deletes the file when dropped.
+    let mut temp_file_guard = TempGuard::default();
+    write_secrets(&cli, &secrets, &mut launch, &mut temp_file_guard)?;
+    finish_env(&cli, &secrets, env_file_vars, &mut launch, &temp_file_guard)?;
+
+    // 4. Execute the Commands and Handle Exit Status
+    let outcome = run_commands(&cli, &secrets, launch, &mut temp_file_guard)?;
+
+    if cli.keep_file {
+        // Persist the files instead of wiping them with the guard
+        let kept: Vec<PathBuf> = std::mem::take(&mut temp_file_guard.files)
+            .into_iter()
+            .filter_map(|file| file.keep().ok().map(|(_, path)| path))
+            .chain(
+                std::mem::take(&mut temp_file_guard.targets)
+                    .into_iter()
+                    .filter_map(|target| target.keep().ok()),
+            )
+            .collect();
+        for path in kept {
+            eprintln!(
+                "{} Warning: Kept the secret file {} -- it is NOT deleted automatically. Remove it when done, or schedule it: echo 'rm -f {}' | at now + 1 hour",
+                RBWCHAIN_PREFIX,
+                path.display(),
+                path.display()
+            );
+        }
+    }
+
+    // Explicitly drop the guard *after* the child process has finished.
+    // This ensures the temp file exists for the duration of the child process.
+    drop(temp_file_guard);
+    if debug_enabled && !cli.file_env_vars.is_empty() {
+         debug_eprintln(debug_enabled, format_args!("Temporary file guard dropped (files deleted)."));
+    }
+
+
+    if outcome.secret_changed && !signals::stop_requested() {
+        // Start over as a fresh run, which fetches the new secrets
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Running rbwchain again with the changed secrets."),
+        );
+        let e = Command::new(env::current_exe()?)
+            .args(env::args_os().skip(1))
+            .exec();
+        return Err(format!("Failed to run rbwchain again: {}", e).into());
+    }
+
+    if outcome.timed_out {
         error_eprintln(format_args!(
-            "The 'rbw' command was not found in your system's PATH."
+            "The command did not finish within {:?} and was stopped.",
+            cli.timeout.unwrap_or_default()
         ));
-        eprintln!(
-            "{} Please ensure rbw (https://github.com/doy/rbw) is installed and accessible.",
-            RBWCHAIN_PREFIX
-        ); // Keep informational part separate
-        std::process::exit(1);
+        return Ok(child::TIMEOUT_EXIT_CODE);
     }
 
-    // 1. Parse Command Line Arguments
-    let cli = Cli::parse();
-    let debug_enabled = cli.debug; // Store flag for easy access
+    // Forward the exit code or signal termination status from the child process.
+    // Pass the debug flag to control the "terminated by signal" message.
+    Ok(exit_code(outcome.status, debug_enabled))
+}
+
+/// Works out the notes to fetch and the source configuration to fetch them
+/// with, syncing the sources first if asked to. When the notes are named by
+/// flag, the positional SECRET_NOTE is moved back to the command.
+fn select_notes(cli: &mut Cli) -> Result<(Vec<NoteSpec>, source::SourceConfig), Box<dyn Error>> {
+    // An item selected by id or URI is looked up as a whole (`rbw get --raw`)
+    let item_selector = cli.item_id.clone().or_else(|| cli.uri.clone());
+    let named_by_flag =
+        !cli.notes.is_empty() || item_selector.is_some() || cli.pick || cli.folder_env.is_some();
+    let mut notes: Vec<NoteSpec> = if !named_by_flag {
+        vec![NoteSpec {
+            name: cli.secret_note.clone(),
+            key_prefix: None,
+        }]
+    } else {
+        // The notes were named by flag, so the positional SECRET_NOTE is the command
+        let command = std::mem::take(&mut cli.secret_note);
+        if !command.is_empty() {
+            cli.command_and_args.insert(0, OsString::from(command));
+        }
+        match &item_selector {
+            Some(selector) => vec![NoteSpec {
+                name: selector.clone(),
+                key_prefix: None,
+            }],
+            // Filled in once the source config is known
+            None if cli.pick || cli.folder_env.is_some() => Vec::new(),
+            None => cli.notes.iter().map(|spec| NoteSpec::parse(spec)).collect(),
+        }
+    };
+    let debug_enabled = cli.debug;
 
     debug_eprintln(debug_enabled, format_args!("Debug mode enabled."));
     debug_eprintln(debug_enabled, format_args!("Parsed arguments: {:?}", cli));
 
-    // 2. Fetch Secret Content (always needed)
-    debug_eprintln(
-        debug_enabled,
-        format_args!("Fetching secret content for note: '{}'", cli.secret_note),
-    );
-    let secret_content = get_secret_content_from_rbw(&cli.secret_note).map_err(|e| {
-        // Ensure the specific error is printed by the main error handler
-        format!(
-            "Error getting secret content from rbw for note '{}': {}",
-            cli.secret_note, e
-        )
-    })?;
-    debug_eprintln(
-        debug_enabled,
-        format_args!(
-            "Successfully fetched {} bytes of secret content.",
-            secret_content.len()
-        ),
-    );
+    let mut source_config = source::SourceConfig {
+        debug: debug_enabled,
+        rbw_path: cli.rbw_path.clone(),
+        rbw_profile: cli.rbw_profile.clone(),
+        raw_item: item_selector.is_some(),
+        login: cli.login.is_some(),
+        totp: cli.totp,
+        folder: cli.folder.clone(),
+        project: cli.project.clone(),
+        database: cli.database.clone(),
+        attachment: cli.attachment.clone(),
+    };
 
-    // 3. Set up the Command
+    if cli.sync || cli.sync_if_older_than.is_some() {
+        let mut synced_notes: Vec<&str> = notes.iter().map(|note| note.name.as_str()).collect();
+        synced_notes.extend(
+            cli.file_env_vars
+                .iter()
+                .filter_map(|spec| spec.note.as_deref()),
+        );
+        if cli.pick || cli.folder_env.is_some() {
+            // Notes yet to be listed come from --source, as an empty note does
+            synced_notes.push("");
+        }
+        sync_sources(
+            &synced_notes,
+            &cli.source,
+            &source_config,
+            cli.sync_if_older_than,
+        );
+    }
+
+    if cli.pick {
+        let picked = pick_note(&cli.source, &source_config)?;
+        debug_eprintln(debug_enabled, format_args!("Picked note: '{}'", picked));
+        notes.push(NoteSpec {
+            name: picked,
+            key_prefix: None,
+        });
+    }
+
+    if let Some(group) = &cli.folder_env {
+        let (names, is_folder) = collect_group_notes(&cli.source, &source_config, group)?;
+        if is_folder {
+            // Fetch the members from that folder, even if names repeat elsewhere
+            source_config.folder = Some(group.clone());
+        }
+        notes.extend(names.into_iter().map(|name| NoteSpec {
+            name,
+            key_prefix: None,
+        }));
+    }
+
+    // Overlays are fetched after, and so win over, the notes selected above
+    notes.extend(cli.overlay.iter().map(|name| NoteSpec {
+        name: name.clone(),
+        key_prefix: None,
+    }));
+
+    if let Some(login_prefix) = &cli.login {
+        if login_prefix.is_none() && item_selector.is_some() {
+            // An id or URL makes a meaningless prefix, such as _3F2A..._USERNAME
+            return Err(
+                "--login needs an explicit PREFIX with --id or --uri (e.g. --login=GITHUB, or --login= for none)."
+                    .into(),
+            );
+        }
+        // Login mode yields USERNAME/PASSWORD/TOTP, prefixed per note unless labeled
+        for note in notes.iter_mut().filter(|n| n.key_prefix.is_none()) {
+            note.key_prefix = match login_prefix {
+                Some(prefix) if prefix.is_empty() => None,
+                Some(prefix) => Some(format!("{}_", prefix)),
+                None => Some(format!(
+                    "{}_",
+                    source::env_var_name(&note.name).to_uppercase()
+                )),
+            };
+        }
+    }
+    Ok((notes, source_config))
+}
+
+/// Reads the Procfile (--procfile) and appends its processes to the command
+/// line as `sh -c` commands separated by `:::`. Returns their names.
+fn read_procfile(cli: &mut Cli) -> Result<Vec<String>, String> {
+    let mut process_names: Vec<String> = Vec::new();
+    let Some(path) = &cli.procfile else {
+        return Ok(process_names);
+    };
+    if let Some(command) = cli.command_and_args.first() {
+        return Err(format!(
+            "--procfile runs the processes of the Procfile; remove the command '{}'.",
+            command.to_string_lossy()
+        ));
+    }
+    let processes = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| procfile::parse(&content))
+        .map_err(|e| format!("Procfile '{}': {}", path.display(), e))?;
+    for (name, command) in processes {
+        if !process_names.is_empty() {
+            cli.command_and_args.push(":::".into());
+        }
+        cli.command_and_args
+            .extend(["sh", "-c", &command].map(OsString::from));
+        process_names.push(name);
+    }
+    Ok(process_names)
+}
+
+/// Fails once a signal asked rbwchain to stop, so that a run interrupted
+/// while it writes the secrets unwinds through the guard removing them.
+fn check_stop() -> Result<(), String> {
+    match signals::stop_signal() {
+        Some(signal) => Err(format!("Stopped by signal {}.", signal)),
+        None => Ok(()),
+    }
+}
+
+/// The attachment (`--attachment`) that -f files get the bytes of
+/// unchanged, even if not UTF-8.
+fn binary_attachment(cli: &Cli) -> Option<&String> {
+    cli.attachment
+        .as_ref()
+        .filter(|_| !cli.file_env_vars.is_empty())
+}
+
+/// Fetches the content of every note in order, or the attachment of each
+/// for -f files with --attachment.
+fn fetch_notes(
+    cli: &Cli,
+    notes: Vec<NoteSpec>,
+    source_config: source::SourceConfig,
+    parse_options: parse::ParseOptions,
+) -> Result<Secrets, Box<dyn Error>> {
+    let mut secret_contents: Vec<(NoteSpec, String)> = Vec::with_capacity(notes.len());
+    let mut field_vars: EnvPairs = Vec::new();
+    let binary_attachment = binary_attachment(cli);
+    let mut attachment_contents: Vec<Vec<u8>> = Vec::new();
+    for note in &notes {
+        if let Some(name) = binary_attachment {
+            let content = fetch_attachment(&note.name, &cli.source, &source_config, name)?;
+            let text = String::from_utf8_lossy(&content).into_owned();
+            secret_contents.push((note.clone(), text));
+            attachment_contents.push(content);
+            continue;
+        }
+        let (content, fields) =
+            fetch_secret_content(&note.name, &cli.source, &source_config, &cli.fields)?;
+        secret_contents.push((note.clone(), content));
+        field_vars.extend(fields);
+    }
+    // The raw content of the notes, byte for byte
+    let raw_content = match binary_attachment {
+        Some(_) => attachment_contents.concat(),
+        None => concat_contents(&secret_contents).into_bytes(),
+    };
+    Ok(Secrets {
+        notes,
+        source_config,
+        parse_options,
+        contents: secret_contents,
+        field_vars,
+        attachment_contents,
+        raw_content,
+    })
+}
+
+/// Checks the commands to run, starting an interactive shell when there is
+/// none. Returns whether that is the case.
+fn prepare_commands(cli: &mut Cli) -> Result<bool, String> {
     // Extract the command and its arguments from the combined list
-    if cli.command_and_args.is_empty() {
-        // This should ideally be caught by clap's 'required=true'
-        error_eprintln(format_args!("No command provided to execute."));
-        return Err("No command specified.".into());
+    // Without a command, an interactive shell gets the secrets
+    let interactive = cli.command_and_args.is_empty();
+    if interactive {
+        if cli.stdin {
+            return Err("--stdin needs a command to read the note content.".to_string());
+        }
+        let shell = env::var_os("SHELL")
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(|| OsString::from("/bin/sh"));
+        eprintln!(
+            "{} Starting {} with the secrets; exit it to drop them.",
+            RBWCHAIN_PREFIX,
+            shell.to_string_lossy()
+        );
+        cli.command_and_args.push(shell);
+    }
+    // Commands separated by `:::` run one after the other
+    let commands: Vec<&[OsString]> = cli.command_and_args.split(|arg| arg == ":::").collect();
+    if commands.iter().any(|command| command.is_empty()) {
+        return Err("A command before or after ':::' is missing.".to_string());
+    }
+    if cli.exec && commands.len() > 1 {
+        return Err(
+            "--exec replaces rbwchain with a single command; it cannot run several separated by ':::'."
+                .to_string(),
+        );
     }
-    let command_to_exec = &cli.command_and_args[0];
-    let command_args = &cli.command_and_args[1..]; // Slice of the remaining elements
+    Ok(interactive)
+}
 
-    // Create the Command process builder
-    let mut command_to_run = Command::new(command_to_exec);
+/// The working directory, account, limits and priorities the commands get.
+fn child_setup(cli: &Cli) -> Result<child::Setup, String> {
+    if let Some(dir) = &cli.chdir {
+        if !dir.is_dir() {
+            return Err(format!("--chdir: '{}' is not a directory.", dir.display()));
+        }
+    }
+    if cli.user.is_some() || cli.group.is_some() {
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        if unsafe { libc::geteuid() } != 0 {
+            return Err("--user and --group require running as root.".to_string());
+        }
+    }
+    let gid = cli.group.or(cli.user.as_ref().and_then(|user| user.gid));
+    if cli.user.is_some() && gid.is_none() {
+        return Err(
+            "The --user id has no account to take the group from; give --group as well."
+                .to_string(),
+        );
+    }
+    Ok(child::Setup {
+        kill_on_parent_exit: cli.kill_on_parent_exit,
+        uid: cli.user.as_ref().map(|user| user.uid),
+        gid,
+        groups: cli.user.as_ref().map(|user| user.groups.clone()),
+        limits: [
+            (child::Limit::Nofile, cli.limit_nofile),
+            (child::Limit::Core, cli.limit_core),
+            (child::Limit::As, cli.limit_as),
+            (child::Limit::Nproc, cli.limit_nproc),
+            (child::Limit::Cpu, cli.limit_cpu),
+        ]
+        .into_iter()
+        .filter_map(|(limit, value)| Some((limit, value?)))
+        .collect(),
+        nice: cli.nice,
+        io_priority: cli.ionice,
+        oom_score_adj: cli.oom_score_adj,
+        umask: cli.umask,
+    })
+}
 
-    // Set the arguments for the command
-    command_to_run.args(command_args);
+/// The part of our own environment that the command inherits.
+fn parent_env(cli: &Cli) -> HashMap<OsString, OsString> {
+    env::vars_os()
+        .filter(|(key, _)| {
+            cli.inherit.is_empty()
+                || cli
+                    .inherit
+                    .iter()
+                    .any(|p| keys::glob_match(p, &key.to_string_lossy()))
+        })
+        .collect()
+}
 
+/// The wrapper's own variables: the RBWCHAIN_* metadata unless --no-meta,
+/// RBWCHAIN_DEBUG, and the prompt of an interactive shell.
+fn wrapper_vars(cli: &Cli, secrets: &Secrets, interactive: bool) -> HashMap<OsString, OsString> {
     // Prepare environment variables map to be passed to the command
     // Use OsString for keys and values to handle non-UTF8 data if necessary,
     // although most interaction here is UTF8 based.
     let mut final_env_vars: HashMap<OsString, OsString> = HashMap::new();
 
-    // Add standard wrapper variables first. These are always set.
-    final_env_vars.insert(
-        "RBWCHAIN_VERSION".into(), // Use .into() for OsString conversion
-        OsString::from(env!("CARGO_PKG_VERSION")),
-    );
-    final_env_vars.insert(
-        "RBWCHAIN_SECRET_NOTE".into(),
-        OsString::from(&cli.secret_note),
-    );
-    if debug_enabled {
-        // Only add RBWCHAIN_DEBUG if debug mode is active
-        final_env_vars.insert("RBWCHAIN_DEBUG".into(), OsString::from("1"));
-    }
+    // Add standard wrapper variables first. These are set unless --no-meta.
+    if !cli.no_meta {
+        final_env_vars.insert(
+            "RBWCHAIN_VERSION".into(), // Use .into() for OsString conversion
+            OsString::from(env!("CARGO_PKG_VERSION")),
+        );
+        final_env_vars.insert(
+            "RBWCHAIN_SECRET_NOTE".into(),
+            // Comma-separated when several notes are merged
+            OsString::from(
+                secrets
+                    .notes
+                    .iter()
+                    .map(|n| n.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        );
+        // Lets wrappers detect a changed secret without seeing its values
+        final_env_vars.insert(
+            "RBWCHAIN_SECRET_HASH".into(),
+            OsString::from(content_hash(&secrets.raw_content)),
+        );
+    }
+    if cli.debug {
+        // Only add RBWCHAIN_DEBUG if debug mode is active
+        final_env_vars.insert("RBWCHAIN_DEBUG".into(), OsString::from("1"));
+    }
+    if interactive {
+        // Marks the prompt; RBWCHAIN_SHELL is for prompts that set their own
+        let prompt = env::var("PS1").unwrap_or_else(|_| "$ ".to_string());
+        final_env_vars.insert("PS1".into(), format!("(rbwchain) {}", prompt).into());
+        final_env_vars.insert("RBWCHAIN_SHELL".into(), OsString::from("1"));
+    }
+    final_env_vars
+}
+
+/// Refuses to write secrets to persistent storage (--require-tmpfs):
+/// checks every location files are about to be written to.
+fn check_tmpfs(cli: &Cli, temp_location: &Path, writes_dir: bool) -> Result<(), String> {
+    let mut locations = Vec::new();
+    if let Some(target) = &cli.file_path {
+        locations.push(match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        });
+    }
+    let temp_files = !cli.file_env_vars.is_empty() && !cli.memfd && cli.file_path.is_none();
+    if temp_files || writes_dir {
+        locations.push(temp_location.to_path_buf());
+    }
+    for location in locations {
+        match perms::is_memory_backed(&location) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(format!(
+                    "{} is not on tmpfs; refusing to write the secret to persistent storage (see --tmpdir).",
+                    location.display()
+                ));
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Cannot determine the filesystem of {}: {}",
+                    location.display(),
+                    e
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hands the secrets to the command the way the flags ask for: as -f files,
+/// a single variable, PEM files, an inherited descriptor, or (by default)
+/// the parsed variables. Files go into `temp_file_guard`, the variables the
+/// command gets into `launch`.
+fn write_secrets(
+    cli: &Cli,
+    secrets: &Secrets,
+    launch: &mut Launch,
+    temp_file_guard: &mut TempGuard,
+) -> Result<(), Box<dyn Error>> {
+    let debug_enabled = cli.debug;
+    let temp_location = temp_location(cli.tmpdir.as_deref(), cli.user.is_some());
+    // Files are handed to the account the command runs as
+    let file_owner = cli.user.as_ref().map(|user| user.uid);
+    let writes_dir =
+        cli.files_dir.is_some() || cli.attachments.is_some() || cli.split_pem.is_some();
+    if !cli.file_env_vars.is_empty() || writes_dir {
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Temporary files go to {}.", temp_location.display()),
+        );
+    }
+    if cli.require_tmpfs {
+        check_tmpfs(cli, &temp_location, writes_dir)?;
+    }
+    if (!cli.file_env_vars.is_empty() && !cli.memfd) || writes_dir || cli.serve {
+        // From the first file on, a signal must not end rbwchain before the
+        // guard removes them
+        signals::record_stops();
+    }
+    let final_env_vars = &mut launch.env;
+
+    if !cli.file_env_vars.is_empty() {
+        // --- File Mode ---
+        write_file_specs(cli, secrets, &temp_location, launch, temp_file_guard)?;
+    } else if let Some(var) = &cli.as_var {
+        // --- Single Variable Mode ---
+        let secret_content = concat_contents(&secrets.contents);
+        let value = secret_content.trim_end_matches(['\n', '\r']);
+        if value.contains('\0') {
+            return Err(
+                "The note content contains a NUL byte and cannot be passed in the environment; use file mode (-f) instead."
+                    .into(),
+            );
+        }
+        debug_eprintln(
+            debug_enabled,
+            format_args!(
+                "Setting {} to the whole note content ({} bytes).",
+                var,
+                value.len()
+            ),
+        );
+        final_env_vars.insert(OsString::from(var), OsString::from(value));
+    } else if let Some(dir_var) = &cli.split_pem {
+        // --- Split PEM Mode ---
+        let files = pem::split(&concat_contents(&secrets.contents))
+            .map_err(|e| format!("Failed to split the note: {}", e))?;
+        let files: HashMap<OsString, OsString> = files
+            .into_iter()
+            .map(|(name, text)| (OsString::from(name), OsString::from(text)))
+            .collect();
+        let dir = write_files_dir(&files, &temp_location, file_owner)?;
+        debug_eprintln(
+            debug_enabled,
+            format_args!(
+                "Wrote {} PEM file(s) to {}, set as {}.",
+                files.len(),
+                dir.path().display(),
+                dir_var
+            ),
+        );
+        final_env_vars.insert(OsString::from(dir_var), dir.path().as_os_str().to_owned());
+        temp_file_guard.dirs.push(dir);
+    } else if let Some(var) = &cli.fd_var {
+        // --- Inherited Descriptor Mode ---
+        let read_end = pipe::inherited(secrets.raw_content.clone())
+            .map_err(|e| format!("Failed to create pipe: {}", e))?;
+        let path = format!("/dev/fd/{}", read_end.as_raw_fd());
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Passing the note content through {} as {}.", path, var),
+        );
+        final_env_vars.insert(OsString::from(var), OsString::from(&path));
+        launch.secret_pipe = Some(read_end.as_raw_fd());
+        temp_file_guard.descriptors.push((read_end, path));
+    } else {
+        // --- Environment Variable Mode (Default Behavior) ---
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Using environment variable mode."),
+        );
+        let parsed_env_vars = note_env_vars(cli, secrets)?;
+
+        if let Some(dir_var) = &cli.files_dir {
+            // One file per variable instead of the variables themselves
+            let dir = write_files_dir(&parsed_env_vars, &temp_location, file_owner)?;
+            debug_eprintln(
+                debug_enabled,
+                format_args!(
+                    "Wrote {} file(s) to {}, set as {}.",
+                    parsed_env_vars.len(),
+                    dir.path().display(),
+                    dir_var
+                ),
+            );
+            final_env_vars.insert(OsString::from(dir_var), dir.path().as_os_str().to_owned());
+            temp_file_guard.dirs.push(dir);
+        } else if cli.serve {
+            // Handed out on request instead of the variables themselves
+            let (dir, socket) = serve::start(
+                &temp_location,
+                file_owner,
+                parsed_env_vars.clone(),
+                debug_enabled,
+            )
+            .map_err(|e| format!("Failed to create the secrets socket: {}", e))?;
+            debug_eprintln(
+                debug_enabled,
+                format_args!(
+                    "Serving {} variable(s) on {}.",
+                    parsed_env_vars.len(),
+                    socket.display()
+                ),
+            );
+            final_env_vars.insert("RBWCHAIN_SOCKET".into(), socket.into_os_string());
+            temp_file_guard.dirs.push(dir);
+            launch.served_vars = parsed_env_vars;
+        } else {
+            final_env_vars.extend(parsed_env_vars);
+        }
+
+        // Calculate counts *after* merging
+        let standard_var_count = if cli.no_meta {0} else {3} + if debug_enabled {1} else {0}; // Base vars + conditional debug var
+        let parsed_count = final_env_vars.len().saturating_sub(standard_var_count);
 
-    // Keep temp file alive until command finishes if using file mode
-    // `NamedTempFile` automatically deletes the file when dropped.
-    let mut temp_file_guard: Option<NamedTempFile> = None;
 
-    if let Some(env_var_spec) = &cli.file_env_var {
-        // --- File Mode ---
-        // Split the spec into ENV_VAR_NAME and an optional extension EXT
-        // We use rsplit_once to get the *last* dot, treating everything before it as the name.
-        let (env_var_name_str, suffix_str) = match env_var_spec.rsplit_once('.') {
-            Some((name, ext)) if !name.is_empty() => (name, Some(format!(".{}", ext))), // Prepend dot if extension exists
-            _ => (env_var_spec.as_str(), None), // No dot, or starts with dot: whole string is name, no suffix
-        };
+        debug_eprintln(
+            debug_enabled,
+            format_args!(
+                "Injecting {} environment variable(s) ({} parsed + {} standard).",
+                final_env_vars.len(),
+                parsed_count,
+                standard_var_count,
+            ),
+        );
+         if debug_enabled {
+            // Optionally log the keys being set (but not the values for security)
+            let keys_str = final_env_vars
+                .keys()
+                .map(|k| k.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ");
+            debug_eprintln(debug_enabled, format_args!("Variables set: [{}]", keys_str));
+         }
+    }
 
-        // Validate that the derived environment variable name is not empty
-        if env_var_name_str.is_empty() {
-            error_eprintln(format_args!(
-                "Invalid value for -f/--file: '{}'. The environment variable name part cannot be empty.",
-                env_var_spec
-            ));
-            return Err("Empty environment variable name derived from file mode spec.".into());
+    if let Some(dir_var) = &cli.attachments {
+        let mut files = HashMap::new();
+        for (note, _) in &secrets.contents {
+            files.extend(fetch_attachments(
+                &note.name,
+                &cli.source,
+                &secrets.source_config,
+            )?);
+            check_stop()?;
         }
+        let dir = write_files_dir(&files, &temp_location, file_owner)?;
+        debug_eprintln(
+            debug_enabled,
+            format_args!(
+                "Wrote {} attachment(s) to {}, set as {}.",
+                files.len(),
+                dir.path().display(),
+                dir_var
+            ),
+        );
+        launch
+            .env
+            .insert(OsString::from(dir_var), dir.path().as_os_str().to_owned());
+        temp_file_guard.dirs.push(dir);
+    }
+    check_stop()?;
+    Ok(())
+}
+
+/// Writes the -f files: each spec's own note, or else the rendered template
+/// or converted notes, or else the notes concatenated in order. They go to
+/// in-memory files (--memfd), the --file-path, or temporary files.
+fn write_file_specs(
+    cli: &Cli,
+    secrets: &Secrets,
+    temp_location: &Path,
+    launch: &mut Launch,
+    temp_file_guard: &mut TempGuard,
+) -> Result<(), Box<dyn Error>> {
+    let debug_enabled = cli.debug;
+    let (source_config, parse_options) = (&secrets.source_config, &secrets.parse_options);
+    let binary_attachment = binary_attachment(cli);
+    let file_owner = cli.user.as_ref().map(|user| user.uid);
+    if cli.file_path.is_some() && cli.file_env_vars.len() > 1 {
+        return Err("--file-path can only be used with a single -f/--file.".into());
+    }
+    if cli.reload_signal.is_some() && cli.watch.is_none() && cli.refresh_file.is_none() {
+        return Err("--reload-signal needs --watch or --refresh-file to notice a change.".into());
+    }
+    // Files generated from the notes rather than holding them verbatim
+    let generated = if let Some(path) = &cli.template {
+        Some(render_template(path, &secrets.contents, parse_options))
+    } else {
+        cli.file_format.map(|format| {
+            note_vars(&secrets.contents, parse_options)
+                .and_then(|vars| convert::render(vars, format))
+        })
+    };
+    let generated = generated.transpose()?;
+    for spec in &cli.file_env_vars {
+        // Each file written so far is removed again if asked to stop
+        check_stop()?;
+        let env_var_name_str = spec.var.as_str();
+        let suffix_str = &spec.suffix;
+        let own_note = spec.note.as_deref();
 
         // Convert the variable name to OsString for insertion into the map
         let env_var_name_os = OsString::from(env_var_name_str);
@@ -270,14 +2331,100 @@ fn main() -> Result<(), Box<dyn Error>> {
             ),
         );
 
+        // The spec's own note, or else the rendered template or converted
+        // notes, or else the notes concatenated in order
+        let secret_content = match (own_note, &generated, binary_attachment) {
+            (Some(note), _, Some(name)) => {
+                let content = fetch_attachment(note, &cli.source, source_config, name)?;
+                launch.own_note_contents.push(content.clone());
+                content
+            }
+            (Some(note), _, None) => {
+                let content = fetch_secret_content(note, &cli.source, source_config, &[])?.0;
+                launch.own_note_contents.push(content.clone().into_bytes());
+                match cli.file_format {
+                    Some(format) => {
+                        let own = NoteSpec {
+                            name: note.to_string(),
+                            key_prefix: None,
+                        };
+                        note_vars(&[(own, content)], parse_options)
+                            .and_then(|vars| convert::render(vars, format))?
+                    }
+                    None => content,
+                }
+                .into_bytes()
+            }
+            (None, Some(generated), _) => generated.clone().into_bytes(),
+            (None, None, _) => secrets.raw_content.clone(),
+        };
+        check_stop()?;
+
+        if cli.memfd {
+            let file = perms::memfd(env_var_name_str, &secret_content)
+                .and_then(|file| {
+                    perms::apply(&file, cli.file_mode, file_owner, cli.file_group).map(|()| file)
+                })
+                .map_err(|e| format!("Failed to create in-memory file: {}", e))?;
+            // The command inherits the descriptor under the same number
+            let path = format!("/proc/self/fd/{}", file.as_raw_fd());
+            debug_eprintln(
+                debug_enabled,
+                format_args!(
+                    "Prepared environment variable: {}={}",
+                    env_var_name_str, path
+                ),
+            );
+            launch.env.insert(env_var_name_os, OsString::from(&path));
+            if cli.reload_signal.is_some() || cli.refresh_file.is_some() {
+                let rewritable = file
+                    .try_clone()
+                    .map_err(|e| format!("Failed to keep in-memory file: {}", e))?;
+                launch
+                    .file_targets
+                    .push((spec.note.clone(), refresh::Target::Descriptor(rewritable)));
+            }
+            temp_file_guard.descriptors.push((file.into(), path));
+            continue;
+        }
+
+        if let Some(target) = &cli.file_path {
+            let path = write_target_file(
+                target,
+                &secret_content,
+                cli.file_mode,
+                file_owner,
+                cli.file_group,
+            )?;
+            debug_eprintln(
+                debug_enabled,
+                format_args!("Wrote secret content to {}.", target.display()),
+            );
+            launch
+                .env
+                .insert(env_var_name_os, path.as_os_str().to_owned());
+            launch
+                .file_targets
+                .push((spec.note.clone(), refresh::Target::Path(path.to_path_buf())));
+            temp_file_guard.targets.push(path);
+            continue;
+        }
+
         // Create temp file using the builder to apply the suffix
         let mut temp_file_builder = Builder::new();
-        if let Some(ref suffix) = suffix_str {
+        let template_suffix;
+        if let Some(template) = &cli.file_name_template {
+            template_suffix = template.suffix(suffix_str.as_deref());
+            temp_file_builder
+                .prefix(&template.prefix)
+                .rand_bytes(template.random)
+                .suffix(&template_suffix);
+        } else if let Some(ref suffix) = suffix_str {
             temp_file_builder.suffix(suffix);
         }
 
         let mut temp_file = temp_file_builder
-            .tempfile() // Creates the named temporary file
+            .tempfile_in(temp_location) // Creates the named temporary file
             .map_err(|e| format!("Failed to create temporary file: {}", e))?;
 
         debug_eprintln(
@@ -291,7 +2438,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         // Write content to temp file
         temp_file
-            .write_all(secret_content.as_bytes())
+            .write_all(&secret_content)
             .map_err(|e| format!("Failed to write secret content to temporary file: {}", e))?;
         debug_eprintln(
             debug_enabled,
@@ -304,14 +2451,29 @@ fn main() -> Result<(), Box<dyn Error>> {
             .map_err(|e| format!("Failed to flush temporary file: {}", e))?;
         debug_eprintln(debug_enabled, format_args!("Flushed temporary file."));
 
+        // Explicitly, whatever the umask or the tempfile defaults are
+        perms::apply(
+            temp_file.as_file(),
+            cli.file_mode,
+            file_owner,
+            cli.file_group,
+        )
+        .map_err(|e| format!("Failed to set permissions of temporary file: {}", e))?;
+
         // Get the path as an OsString (needed for .env)
         let temp_file_path_os = temp_file.path().as_os_str().to_os_string();
 
         // Add the *parsed* environment variable name pointing to the *path* of the temp file.
-        final_env_vars.insert(env_var_name_os.clone(), temp_file_path_os.clone());
+        launch
+            .env
+            .insert(env_var_name_os.clone(), temp_file_path_os.clone());
 
-        // Move the temp_file into the guard to keep it alive until the end of `main`.
-        temp_file_guard = Some(temp_file);
+        // Move the temp_file into the guard to keep it alive until the command is done.
+        launch.file_targets.push((
+            spec.note.clone(),
+            refresh::Target::Path(temp_file.path().to_path_buf()),
+        ));
+        temp_file_guard.files.push(temp_file);
 
         debug_eprintln(
             debug_enabled,
@@ -321,16 +2483,36 @@ fn main() -> Result<(), Box<dyn Error>> {
                 temp_file_path_os.to_string_lossy() // Log path lossily
             ),
         );
-    } else {
-        // --- Environment Variable Mode (Default Behavior) ---
-        debug_eprintln(
-            debug_enabled,
-            format_args!("Using environment variable mode."),
-        );
+    }
+    Ok(())
+}
 
+/// Parses the notes into variables, later notes overriding earlier ones,
+/// and applies the key rules (--only, --rename, ...) and --decode64.
+fn note_env_vars(cli: &Cli, secrets: &Secrets) -> Result<HashMap<OsString, OsString>, String> {
+    let debug_enabled = cli.debug;
+    let key_rules = keys::KeyRules {
+        only: cli.only.clone(),
+        exclude: cli.exclude.clone(),
+        renames: cli.renames.clone(),
+        strip_prefix: cli.strip_prefix.clone(),
+        prefix: cli.prefix.clone(),
+        case: if cli.upcase_keys {
+            Some(keys::Case::Upper)
+        } else if cli.downcase_keys {
+            Some(keys::Case::Lower)
+        } else {
+            None
+        },
+    };
+    let mut parsed_env_vars: HashMap<OsString, OsString> = HashMap::new();
+    let mut invalid_keys = Vec::new();
+    // The note each variable was last set by, for the debug log
+    let mut layers: HashMap<String, &str> = HashMap::new();
+    for (note, secret_content) in &secrets.contents {
         // Parse the fetched content into environment variables (String -> String)
-        // Pass the debug flag to control warnings during parsing
-        let parsed_vars = parse_env_vars(&secret_content, debug_enabled)?;
+        let parsed_vars = parse::parse_env_vars(secret_content, &secrets.parse_options)
+            .map_err(|e| format!("Failed to parse secret note '{}': {}", note.name, e))?;
 
         if parsed_vars.is_empty() && !secret_content.trim().is_empty() {
             // Only warn if the secret content wasn't empty but we didn't parse anything.
@@ -338,133 +2520,680 @@ fn main() -> Result<(), Box<dyn Error>> {
                 debug_enabled,
                 format_args!(
                     "No valid 'KEY=VALUE' pairs found in secret note '{}'.",
-                    cli.secret_note
+                    note.name
                 ),
             );
         }
 
-        // Merge parsed vars into final_env_vars. Parsed vars take precedence if keys conflict.
+        // Merge parsed vars into final_env_vars. Parsed vars take precedence if keys conflict,
+        // and later notes override earlier ones.
         // Convert String key/value from parsed_vars to OsString for the final map.
+        let prefix = note.key_prefix.as_deref().unwrap_or_default();
+        let mut parsed_vars: Vec<(String, String)> = parsed_vars
+            .into_iter()
+            .map(|(key, value)| (format!("{}{}", prefix, key), value))
+            .collect();
+        parsed_vars.sort_by_key(|(key, _)| key_rules.overrides(key));
         for (key, value) in parsed_vars {
-            final_env_vars.insert(OsString::from(key), OsString::from(value));
+            let Some(mut key) = key_rules.apply(&key) else {
+                continue;
+            };
+            // With --files-dir, keys name files (`tls.crt`) rather than variables
+            let valid = if cli.files_dir.is_some() {
+                is_file_name(&key)
+            } else {
+                parse::is_env_name(&key)
+            };
+            if !valid {
+                if !cli.sanitize_keys {
+                    invalid_keys.push(key);
+                    continue;
+                }
+                let sanitized = source::env_var_name(&key);
+                debug_eprintln(
+                    debug_enabled,
+                    format_args!("Sanitized key '{}' to '{}'.", key, sanitized),
+                );
+                key = sanitized;
+            }
+            if secrets.notes.len() > 1 {
+                layers.insert(key.clone(), &note.name);
+            }
+            parsed_env_vars.insert(OsString::from(key), OsString::from(value));
+        }
+    }
+    let mut layers: Vec<(String, &str)> = layers.into_iter().collect();
+    layers.sort();
+    for (key, note) in layers {
+        debug_eprintln(
+            debug_enabled,
+            format_args!("{} comes from '{}'.", key, note),
+        );
+    }
+
+    if !invalid_keys.is_empty() {
+        let names = invalid_keys
+            .iter()
+            .map(|k| format!("'{}'", k))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if cli.files_dir.is_some() {
+            return Err(format!(
+                "Invalid file name(s): {}. Names must not be empty, '.' or '..', or contain '/'; use --sanitize-keys to convert them.",
+                names
+            ));
         }
+        return Err(format!(
+            "Invalid environment variable name(s): {}. Names may only contain letters, digits and '_' and must not start with a digit; use --sanitize-keys to convert them.",
+            names
+        ));
+    }
 
-        // Calculate counts *after* merging
-        let standard_var_count = 2 + if debug_enabled {1} else {0}; // Base vars + conditional debug var
-        let parsed_count = final_env_vars.len().saturating_sub(standard_var_count);
+    decode_base64_values(&mut parsed_env_vars, &cli.decode64)?;
+    Ok(parsed_env_vars)
+}
+
+/// Completes the command's environment with the local env files, --field
+/// values, exported and literal variables, then checks it: --no-override,
+/// --require, --validate, values leaked into the command line, and the
+/// size limit of exec.
+fn finish_env(
+    cli: &Cli,
+    secrets: &Secrets,
+    env_file_vars: HashMap<String, String>,
+    launch: &mut Launch,
+    temp_file_guard: &TempGuard,
+) -> Result<(), String> {
+    let debug_enabled = cli.debug;
+    let (final_env_vars, parent_env) = (&mut launch.env, &launch.parent_env);
+    // The fetched values, to make sure none of them leaks into the command line
+    let temp_paths = temp_file_guard.paths();
+    let mut secret_values: Vec<(String, OsString)> = final_env_vars
+        .iter()
+        .map(|(key, value)| (key.to_string_lossy().into_owned(), value))
+        .filter(|(key, value)| {
+            !key.starts_with("RBWCHAIN_")
+                && !temp_paths.contains(&value.as_os_str())
+                && !temp_file_guard
+                    .descriptors
+                    .iter()
+                    .any(|(_, path)| value.as_os_str() == path.as_str())
+        })
+        .map(|(key, value)| (key, value.clone()))
+        .collect();
+    secret_values.extend(
+        secrets
+            .field_vars
+            .iter()
+            .map(|(key, value)| (key.clone(), OsString::from(value))),
+    );
+    secret_values.extend(
+        launch
+            .served_vars
+            .iter()
+            .map(|(key, value)| (key.to_string_lossy().into_owned(), value.clone())),
+    );
+
+    // Local env files fill in around the notes' variables, or override them
+    for (key, value) in env_file_vars {
+        let key = OsString::from(key);
+        if cli.env_file_override || !final_env_vars.contains_key(&key) {
+            final_env_vars.insert(key, OsString::from(value));
+        }
+    }
+    // Explicitly requested fields win over parsed variables in either mode
+    for (env_var, value) in &secrets.field_vars {
+        final_env_vars.insert(OsString::from(env_var), OsString::from(value));
+    }
+    // Audit which fetched variables shadow exported ones
+    let (overridden, new) = collisions(final_env_vars, parent_env);
+    if cli.report_collisions || debug_enabled {
+        let verb = match cli.precedence {
+            Precedence::Parent => "Keeping",
+            Precedence::Secret => "Overriding",
+        };
+        eprintln!(
+            "{} {} {} exported variable(s): {}",
+            RBWCHAIN_PREFIX,
+            verb,
+            overridden.len(),
+            overridden.join(", ")
+        );
+        eprintln!(
+            "{} Adding {} new variable(s): {}",
+            RBWCHAIN_PREFIX,
+            new.len(),
+            new.join(", ")
+        );
+    }
+    if cli.no_override && !overridden.is_empty() {
+        return Err(format!(
+            "Refusing to override exported variable(s): {}. Unset them or use --precedence parent to keep them.",
+            overridden.join(", ")
+        ));
+    }
+    if cli.precedence == Precedence::Parent {
+        // Exported values win, so checks below see what the command will get.
+        // The wrapper's own variables are always set.
+        for (key, value) in final_env_vars.iter_mut() {
+            if key.to_string_lossy().starts_with("RBWCHAIN_") {
+                continue;
+            }
+            if let Some(parent_value) = parent_env.get(key) {
+                *value = parent_value.clone();
+            }
+        }
+    }
+    // Literal variables from the command line win over everything fetched
+    for (key, value) in &cli.literal_vars {
+        final_env_vars.insert(OsString::from(key), OsString::from(value));
+    }
 
+    // Fail fast if the notes lack variables the command depends on, served
+    // ones included
+    let mut checked_vars = launch.served_vars.clone();
+    checked_vars.extend(final_env_vars.clone());
+    let missing = missing_required(&cli.require, &checked_vars);
+    if !missing.is_empty() {
+        return Err(format!(
+            "Required variable(s) missing or empty: {}",
+            missing.join(", ")
+        ));
+    }
+    let invalid = validate::failures(&cli.validate, &checked_vars);
+    if !invalid.is_empty() {
+        return Err(format!("Invalid value(s): {}", invalid.join("; ")));
+    }
+    let leaked = leaked_secrets(&cli.command_and_args, &secret_values);
+    if !leaked.is_empty() {
+        let message = format!(
+            "The command line contains the value of {}. Arguments are visible to other users of this machine (e.g. in /proc); read the value from the environment instead.",
+            leaked.join(", ")
+        );
+        if cli.deny_args_leak {
+            return Err(message);
+        }
+        eprintln!("{} Warning: {}", RBWCHAIN_PREFIX, message);
+    }
+    limits::check_exec_size(&cli.command_and_args, parent_env, final_env_vars)?;
 
+    if !cli.inherit.is_empty() {
         debug_eprintln(
             debug_enabled,
             format_args!(
-                "Injecting {} environment variable(s) ({} parsed + {} standard).",
-                final_env_vars.len(),
-                parsed_count,
-                standard_var_count,
+                "Inheriting {} variable(s) from the parent environment.",
+                parent_env.len()
             ),
         );
-         if debug_enabled {
-            // Optionally log the keys being set (but not the values for security)
-            let keys_str = final_env_vars
-                .keys()
-                .map(|k| k.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(", ");
-            debug_eprintln(debug_enabled, format_args!("Variables set: [{}]", keys_str));
-         }
     }
+    Ok(())
+}
 
-    // Set the environment variables for the command
-    command_to_run.envs(&final_env_vars);
+/// Runs the --check and --pre hooks, then the commands (in parallel, or one
+/// after the other unless --exec replaces rbwchain with the only one), and
+/// the --post hooks, watching the notes meanwhile if asked to.
+fn run_commands(
+    cli: &Cli,
+    secrets: &Secrets,
+    launch: Launch,
+    temp_file_guard: &mut TempGuard,
+) -> Result<Outcome, Box<dyn Error>> {
+    let debug_enabled = cli.debug;
+    let Launch {
+        setup,
+        parent_env,
+        env: final_env_vars,
+        served_vars: _,
+        mut file_targets,
+        own_note_contents,
+        mut secret_pipe,
+        mut process_names,
+    } = launch;
+    let notes = &secrets.notes;
+    let binary_attachment = binary_attachment(cli);
+    // Commands separated by `:::` run one after the other
+    let commands: Vec<&[OsString]> = cli.command_and_args.split(|arg| arg == ":::").collect();
 
-    // Ensure the child process inherits stdin, stdout, and stderr from the wrapper.
-    command_to_run.stdin(Stdio::inherit());
-    command_to_run.stdout(Stdio::inherit());
-    command_to_run.stderr(Stdio::inherit());
+    // Create the Command process builder for each of the commands
+    let build_command = |command_to_exec: &OsString, command_args: &[OsString]| {
+        let mut command_to_run = Command::new(command_to_exec);
+        if cli.timeout.is_some() {
+            // Its own process group, so that the timeout stops its children too
+            command_to_run.process_group(0);
+        }
+        if let Some(name) = &cli.argv0 {
+            command_to_run.arg0(name);
+        }
+        if let Some(dir) = &cli.chdir {
+            command_to_run.current_dir(dir);
+        }
+        setup.clone().apply(&mut command_to_run);
 
-    debug_eprintln(
-        debug_enabled,
-        format_args!(
-            "Executing command: {} {}",
-            command_to_exec.to_string_lossy(),
-            command_args
-                .iter()
-                .map(|a| a.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(" ")
-        ),
-    );
+        // Set the arguments for the command
+        command_to_run.args(command_args);
 
-    // 4. Execute the Command and Handle Exit Status
-    let status = command_to_run
-        .status()
-        .map_err(|e| format!("Failed to execute command '{}': {}", command_to_exec.to_string_lossy(), e))?; // Use extracted command in error
+        if !cli.inherit.is_empty() {
+            command_to_run.env_clear();
+            command_to_run.envs(&parent_env);
+        }
+        // Set the environment variables for the command
+        command_to_run.envs(&final_env_vars);
 
-    debug_eprintln(
-        debug_enabled,
-        format_args!("Command finished with status: {}", status),
-    );
+        // Ensure the child process inherits stdin, stdout, and stderr from the wrapper.
+        if cli.stdin {
+            command_to_run.stdin(Stdio::piped());
+        } else {
+            command_to_run.stdin(Stdio::inherit());
+        }
+        command_to_run.stdout(Stdio::inherit());
+        command_to_run.stderr(Stdio::inherit());
+        command_to_run
+    };
 
-    // Explicitly drop the guard *after* the child process has finished.
-    // This ensures the temp file exists for the duration of the child process.
-    drop(temp_file_guard);
-    if debug_enabled && cli.file_env_var.is_some() {
-         debug_eprintln(debug_enabled, format_args!("Temporary file guard dropped (file deleted)."));
+    #[cfg(target_os = "linux")]
+    if cli.init && std::process::id() != 1 {
+        // Outside of PID 1, orphans only come to rbwchain when asked for
+        child::become_subreaper()
+            .map_err(|e| format!("--init: Failed to become a subreaper: {}", e))?;
     }
 
+    // Hooks run with `sh -c` in the command's environment
+    let run_hook = |flag: &str, script: &str, extra_env: &[(&str, String)]| {
+        let mut hook = build_command(
+            &OsString::from("sh"),
+            &[OsString::from("-c"), OsString::from(script)],
+        );
+        // --argv0 and --stdin are for the command
+        hook.arg0("sh").stdin(Stdio::inherit());
+        hook.envs(extra_env.iter().map(|(key, value)| (key, value)));
+        debug_eprintln(debug_enabled, format_args!("Running {} '{}'", flag, script));
+        let mut child = hook
+            .spawn()
+            .map_err(|e| format!("Failed to execute {} '{}': {}", flag, script, e))?;
+        signals::forward_to(child.id(), cli.timeout.is_some(), cli.init);
+        let status = child::wait(&mut child, cli.init)
+            .map_err(|e| format!("Failed to wait for {} '{}': {}", flag, script, e))?;
+        signals::stop_forwarding();
+        Ok::<_, String>(status)
+    };
+    let hook_failed = |flag: &str, script: &str, status: ExitStatus| {
+        error_eprintln(format_args!(
+            "The {} '{}' failed ({}).",
+            flag, script, status
+        ));
+    };
 
-    // Forward the exit code or signal termination status from the child process.
-    // Pass the debug flag to control the "terminated by signal" message.
-    handle_exit_status(status, debug_enabled);
+    let mut outcome = (ExitStatus::default(), false);
+    for script in &cli.check {
+        let mut retries = cli.check_retries;
+        loop {
+            outcome.0 = run_hook("--check", script, &[])?;
+            if outcome.0.success() || retries == 0 || signals::stop_requested() {
+                break;
+            }
+            debug_eprintln(
+                debug_enabled,
+                format_args!(
+                    "--check '{}' failed ({}); retrying in {:?}.",
+                    script, outcome.0, cli.check_interval
+                ),
+            );
+            retries -= 1;
+            thread::sleep(cli.check_interval);
+        }
+        if !outcome.0.success() {
+            hook_failed("--check", script, outcome.0);
+            break;
+        }
+    }
+    if outcome.0.success() {
+        for script in &cli.pre {
+            outcome.0 = run_hook("--pre", script, &[])?;
+            if !outcome.0.success() {
+                hook_failed("--pre", script, outcome.0);
+                break;
+            }
+        }
+    }
+    let pre_succeeded = outcome.0.success();
+    let watch_interval = cli.watch.or(cli.refresh_file);
+    let watcher = watch_interval.filter(|_| pre_succeeded).map(|interval| {
+        // The notes, followed by those of -f specs with a note of their own
+        let own_notes = cli
+            .file_env_vars
+            .iter()
+            .filter_map(|spec| spec.note.clone());
+        let names: Vec<String> = notes
+            .iter()
+            .map(|note| note.name.clone())
+            .chain(own_notes)
+            .collect();
+        let mut contents: Vec<Vec<u8>> = match binary_attachment {
+            Some(_) => secrets.attachment_contents.clone(),
+            None => secrets
+                .contents
+                .iter()
+                .map(|(_, content)| content.clone().into_bytes())
+                .collect(),
+        };
+        contents.extend(own_note_contents.iter().cloned());
+        let (flag_source, config) = (cli.source.clone(), secrets.source_config.clone());
+        let attachment = binary_attachment.cloned();
+        let sync = cli.watch_sync;
+        let on_change = if cli.reload_signal.is_some() || cli.refresh_file.is_some() {
+            watch::OnChange::Reload {
+                signal: cli.reload_signal,
+                rewrite: file_rewriter(
+                    cli,
+                    notes,
+                    std::mem::take(&mut file_targets),
+                    &secrets.parse_options,
+                    cli.user.as_ref().map(|user| user.uid),
+                ),
+            }
+        } else {
+            watch::OnChange::Restart
+        };
+        debug_eprintln(
+            debug_enabled,
+            format_args!("Watching the notes for changes every {:?}.", interval),
+        );
+        watch::start(
+            interval,
+            watch::digest(&contents),
+            move || refetch_contents(&names, &flag_source, &config, attachment.as_deref(), sync),
+            on_change,
+            debug_enabled,
+        )
+    });
+    let secret_changed = || watcher.as_ref().is_some_and(watch::Watcher::changed);
+    if !pre_succeeded {
+        // A --check or --pre failed: the command does not run, and neither
+        // do the --post hooks
+    } else if cli.parallel || cli.procfile.is_some() {
+        let programs: Vec<&OsStr> = commands
+            .iter()
+            .map(|command| command[0].as_os_str())
+            .collect();
+        if process_names.is_empty() {
+            process_names = parallel::names(&programs);
+        }
+        let named = process_names
+            .into_iter()
+            .zip(&commands)
+            .map(|(name, command)| {
+                let mut command_to_run = build_command(&command[0], &command[1..]);
+                if let Some(fd) = secret_pipe.take() {
+                    pipe::pass_to(&mut command_to_run, fd);
+                }
+                if !cli.stdin {
+                    // Outside of the terminal's foreground, reading it would stop them
+                    command_to_run.stdin(Stdio::null());
+                }
+                (name, command_to_run)
+            })
+            .collect();
+        let input = cli.stdin.then(|| secrets.raw_content.clone());
+        let stop_others = cli.procfile.is_some();
+        outcome.0 = parallel::run(named, input, stop_others, cli.init, debug_enabled)
+            .map_err(|e| e.to_string())?;
+    } else {
+        for command in &commands {
+            if secret_changed() {
+                break;
+            }
+            check_stop()?;
+            let (command_to_exec, command_args) = (&command[0], &command[1..]);
+            let mut command_to_run = build_command(command_to_exec, command_args);
+            if let Some(fd) = secret_pipe.take() {
+                pipe::pass_to(&mut command_to_run, fd);
+            }
 
-    // Note: handle_exit_status never returns (it exits).
-    // The Ok(()) below is technically unreachable but needed for the type signature.
-    // Ok(()) // Not strictly needed as handle_exit_status diverges
-}
+            debug_eprintln(
+                debug_enabled,
+                format_args!(
+                    "Executing command: {} {}",
+                    command_to_exec.to_string_lossy(),
+                    command_args
+                        .iter()
+                        .map(|a| a.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            );
 
-// --- Exit Status Handling ---
-/// Handles the ExitStatus of the child process, exiting the wrapper
-/// with the appropriate code or signal status.
-/// Uses `debug_eprintln` for the signal termination message.
-///
-/// This function diverges (never returns).
-fn handle_exit_status(status: ExitStatus, debug_enabled: bool) -> ! {
-    // Check if the process exited normally
-    if let Some(code) = status.code() {
-        // Exit the wrapper with the same code as the child process
-        std::process::exit(code);
-    } else {
-        // The process was terminated by a signal (Unix-specific)
-        #[cfg(unix)]
-        {
-            if let Some(signal) = status.signal() {
-                // As per convention (e.g., bash), exit code for signal termination
-                // is 128 + signal number.
-                let exit_code = 128 + signal;
-                // Use the conditional debug printer for this message
+            if cli.exec {
+                // Replace rbwchain with the command; this only returns on failure
+                let guard = std::mem::take(temp_file_guard);
+                if !guard.paths().is_empty() {
+                    guard
+                        .detach()
+                        .map_err(|e| format!("Failed to start the cleanup process: {}", e))?;
+                } else {
+                    // The descriptors the command inherits must stay open
+                    std::mem::forget(guard);
+                }
+                let e = command_to_run.exec();
+                return Err(format!(
+                    "Failed to execute command '{}': {}",
+                    command_to_exec.to_string_lossy(),
+                    e
+                )
+                .into());
+            }
+
+            let mut child = command_to_run.spawn().map_err(|e| {
+                // Use extracted command in error
+                format!(
+                    "Failed to execute command '{}': {}",
+                    command_to_exec.to_string_lossy(),
+                    e
+                )
+            })?;
+            if let Some(stdin) = child.stdin.take() {
                 debug_eprintln(
                     debug_enabled,
-                    format_args!(
-                        "Child process terminated by signal {} (Exiting with code {})",
-                        signal, exit_code
-                    ),
+                    format_args!("Writing the note content to the command's stdin."),
                 );
-                std::process::exit(exit_code);
-            } else {
-                // Should not happen if code() is None on Unix, but handle defensively.
-                // This is unexpected, treat as an error message.
-                error_eprintln(format_args!(
-                    "Child process terminated abnormally (unknown Unix reason)."
-                ));
-                std::process::exit(1); // General error
+                pipe::feed(stdin, secrets.raw_content.clone());
+            }
+            if !temp_file_guard.paths().is_empty()
+                || cli.timeout.is_some()
+                || cli.init
+                || cli.supervised
+                || watcher.is_some()
+            {
+                // Stay alive until the command ends, to clean up after it (or, when
+                // supervised, so that stopping the supervisor reaches the command;
+                // or watching, to stop it for a restart)
+                signals::forward_to(child.id(), cli.timeout.is_some(), cli.init);
+                if let Some(signal) = signals::stop_signal() {
+                    // It came while the command started, before it could be passed on
+                    signals::send(signal);
+                }
+            }
+            let (status, timed_out) = match cli.timeout {
+                Some(timeout) => child::wait_with_timeout(
+                    &mut child,
+                    timeout,
+                    cli.kill_after,
+                    cli.init,
+                    debug_enabled,
+                ),
+                None => child::wait(&mut child, cli.init).map(|status| (status, false)),
+            }
+            .map_err(|e| format!("Failed to wait for command: {}", e))?;
+            signals::stop_forwarding();
+
+            debug_eprintln(
+                debug_enabled,
+                format_args!("Command finished with status: {}", status),
+            );
+            outcome = (status, timed_out);
+            if !status.success() || timed_out || signals::stop_requested() {
+                // Like `&&` in a shell, a failure skips the commands after it
+                break;
             }
         }
-        #[cfg(not(unix))]
-        {
-            // On non-Unix platforms, if code() is None, it's an abnormal termination.
-            // Treat as an error message.
-            error_eprintln(format_args!("Child process terminated abnormally."));
-            std::process::exit(1); // General error
+    }
+    let (mut status, timed_out) = outcome;
+    if let Some(watcher) = &watcher {
+        watcher.stop();
+    }
+    if pre_succeeded {
+        let exit_code = if timed_out {
+            child::TIMEOUT_EXIT_CODE
+        } else {
+            status
+                .code()
+                .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+        };
+        let extra_env = [("RBWCHAIN_EXIT_CODE", exit_code.to_string())];
+        for script in &cli.post {
+            let post_status = run_hook("--post", script, &extra_env)?;
+            if !post_status.success() {
+                hook_failed("--post", script, post_status);
+            }
+            if status.success() && !post_status.success() {
+                // A failed teardown fails a run that otherwise succeeded
+                status = post_status;
+            }
         }
     }
+    Ok(Outcome {
+        status,
+        timed_out,
+        secret_changed: secret_changed(),
+    })
+}
+
+// --- Exit Status Handling ---
+/// Maps the ExitStatus of the child process to the code the wrapper exits
+/// with: the same code, or 128 + the signal that terminated it.
+/// Uses `debug_eprintln` for the signal termination message.
+fn exit_code(status: ExitStatus, debug_enabled: bool) -> i32 {
+    // Check if the process exited normally
+    if let Some(code) = status.code() {
+        // Exit the wrapper with the same code as the child process
+        return code;
+    }
+    // The process was terminated by a signal (Unix-specific)
+    let Some(signal) = status.signal() else {
+        // Should not happen if code() is None on Unix, but handle defensively.
+        // This is unexpected, treat as an error message.
+        error_eprintln(format_args!(
+            "Child process terminated abnormally (unknown Unix reason)."
+        ));
+        return 1; // General error
+    };
+    // As per convention (e.g., bash), exit code for signal termination
+    // is 128 + signal number.
+    let exit_code = 128 + signal;
+    // Use the conditional debug printer for this message
+    debug_eprintln(
+        debug_enabled,
+        format_args!(
+            "Child process terminated by signal {} (Exiting with code {})",
+            signal, exit_code
+        ),
+    );
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_spec(spec: &str, var: &str, suffix: Option<&str>, note: Option<&str>) {
+        let parsed = FileSpec::parse(spec).unwrap();
+        assert_eq!(parsed.var, var, "{}", spec);
+        assert_eq!(parsed.suffix.as_deref(), suffix, "{}", spec);
+        assert_eq!(parsed.note.as_deref(), note, "{}", spec);
+    }
+
+    #[test]
+    fn file_spec_forms() {
+        assert_spec("CERT", "CERT", None, None);
+        assert_spec("CERT.pem", "CERT", Some(".pem"), None);
+        assert_spec("A.B.json", "A.B", Some(".json"), None);
+        assert_spec(".env", ".env", None, None);
+        assert_spec("CERT:pem", "CERT", Some(".pem"), None);
+        assert_spec("CERT:.pem", "CERT", Some(".pem"), None);
+        assert_spec("my.var:", "my.var", None, None);
+        assert_spec("CERT:pem=tls-cert", "CERT", Some(".pem"), Some("tls-cert"));
+        assert_spec("KEY=ssh/key=1", "KEY", None, Some("ssh/key=1"));
+    }
+
+    #[test]
+    fn file_spec_errors() {
+        assert!(FileSpec::parse("CERT=").is_err());
+        assert!(FileSpec::parse(":pem").is_err());
+        assert!(FileSpec::parse("").is_err());
+    }
+
+    #[test]
+    fn name_template_parts() {
+        let template = NameTemplate::parse("app-XXXX.{ext}").unwrap();
+        assert_eq!(template.prefix, "app-");
+        assert_eq!(template.random, 4);
+        assert_eq!(template.suffix, ".{ext}");
+
+        // Only the last run of X is random
+        let template = NameTemplate::parse("XX-box-XXX").unwrap();
+        assert_eq!(template.prefix, "XX-box-");
+        assert_eq!(template.random, 3);
+        assert_eq!(template.suffix, "");
+
+        let template = NameTemplate::parse("fixed.conf").unwrap();
+        assert_eq!(template.prefix, "");
+        assert_eq!(template.random, 0);
+        assert_eq!(template.suffix, "fixed.conf");
+    }
+
+    #[test]
+    fn name_template_errors() {
+        assert!(NameTemplate::parse("").is_err());
+        assert!(NameTemplate::parse("dir/XXXX").is_err());
+    }
+
+    #[test]
+    fn name_template_extension() {
+        let template = NameTemplate::parse("app-XXXX.{ext}").unwrap();
+        assert_eq!(template.suffix(Some(".pem")), ".pem");
+        assert_eq!(template.suffix(None), "");
+        let template = NameTemplate::parse("XXXX-{ext}.txt").unwrap();
+        assert_eq!(template.suffix(Some(".pem")), "-pem.txt");
+        assert_eq!(template.suffix(None), "-.txt");
+    }
+
+    fn expanded(args: &[&str]) -> Vec<OsString> {
+        expand_subcommand(args.iter().map(OsString::from).collect())
+    }
+
+    fn os_strings(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn subcommands_become_flags() {
+        assert_eq!(
+            expanded(&["rbwchain", "up", "dev"]),
+            os_strings(&["rbwchain", "--procfile", "Procfile", "dev"])
+        );
+        assert_eq!(
+            expanded(&["rbwchain", "--supervised", "up", "dev"]),
+            os_strings(&["rbwchain", "--supervised", "--procfile", "Procfile", "dev"])
+        );
+        assert_eq!(
+            expanded(&["rbwchain", "up", "--procfile=P", "dev"]),
+            os_strings(&["rbwchain", "--procfile=P", "dev"])
+        );
+        assert_eq!(
+            expanded(&["rbwchain", "serve", "app", "sh"]),
+            os_strings(&["rbwchain", "--serve", "app", "sh"])
+        );
+        // Anywhere else, they are a note or an argument
+        let unchanged = ["rbwchain", "-n", "up", "serve"];
+        assert_eq!(expanded(&unchanged), os_strings(&unchanged));
+    }
 }
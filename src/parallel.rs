@@ -0,0 +1,202 @@
+//! Running several commands at once (`--parallel`, `--procfile`), with their output
+//! multiplexed line by line behind the name of each.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{debug_eprintln, pipe, signals};
+
+/// Names the commands after their programs, numbering repeated names.
+pub fn names(programs: &[&OsStr]) -> Vec<String> {
+    let base: Vec<String> = programs
+        .iter()
+        .map(|program| {
+            Path::new(program)
+                .file_name()
+                .unwrap_or(program)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    base.iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if base.iter().filter(|other| *other == name).count() == 1 {
+                return name.clone();
+            }
+            let n = base[..=i].iter().filter(|other| *other == name).count();
+            format!("{}.{}", name, n)
+        })
+        .collect()
+}
+
+/// Copies the lines of `reader` to stdout (or stderr) behind `prefix`.
+/// Whole lines are written at once, so output of the commands does not mix
+/// within a line.
+fn relay(reader: impl Read + Send + 'static, prefix: String, to_stderr: bool) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if !line.ends_with(b"\n") {
+                line.push(b'\n');
+            }
+            let mut prefixed = prefix.clone().into_bytes();
+            prefixed.extend_from_slice(&line);
+            let _ = if to_stderr {
+                io::stderr().lock().write_all(&prefixed)
+            } else {
+                io::stdout().lock().write_all(&prefixed)
+            };
+        }
+    })
+}
+
+/// How often the commands are checked on when they are not reaped as a whole.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits for any of `children` to exit and returns its index and status.
+/// With `reap`, processes that are not among them, e.g. orphans inherited
+/// as an init, are reaped along the way. Otherwise only `children` are
+/// waited for, so that other threads can wait for processes of their own.
+fn wait_any(
+    children: &mut [Child],
+    running: &[bool],
+    reap: bool,
+) -> io::Result<(usize, ExitStatus)> {
+    loop {
+        if !reap {
+            for (index, child) in children.iter_mut().enumerate() {
+                if running[index] {
+                    if let Some(status) = child.try_wait()? {
+                        return Ok((index, status));
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        let mut status = 0;
+        // SAFETY: `status` is a valid place for waitpid to write to.
+        let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+        if pid == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        let found = children
+            .iter()
+            .enumerate()
+            .position(|(i, child)| running[i] && child.id() == pid as u32);
+        if let Some(index) = found {
+            return Ok((index, ExitStatus::from_raw(status)));
+        }
+    }
+}
+
+/// Runs the named `commands` at the same time in a process group of their
+/// own, which receives the signals rbwchain gets, and feeds each `input`
+/// on stdin if given. Returns the status of the first command that failed,
+/// or success if none did. With `stop_others`, the first command to exit
+/// stops the rest with SIGTERM and its status is returned instead.
+pub fn run(
+    commands: Vec<(String, Command)>,
+    input: Option<Vec<u8>>,
+    stop_others: bool,
+    init: bool,
+    debug: bool,
+) -> io::Result<ExitStatus> {
+    let width = commands
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut names = Vec::with_capacity(commands.len());
+    let mut children: Vec<Child> = Vec::with_capacity(commands.len());
+    let mut relays = Vec::new();
+    for (name, mut command) in commands {
+        // A zombie leader still holds the group, so later commands can join
+        let group = children.first().map_or(0, |leader| leader.id() as i32);
+        command.process_group(group);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(leader) = children.first() {
+                    // SAFETY: kill has no memory safety preconditions.
+                    unsafe { libc::kill(-(leader.id() as i32), libc::SIGTERM) };
+                }
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to execute command '{}': {}", name, e),
+                ));
+            }
+        };
+        debug_eprintln(
+            debug,
+            format_args!("Started '{}' as process {}.", name, child.id()),
+        );
+        let prefix = format!("{:width$} | ", name, width = width);
+        if let (Some(stdin), Some(input)) = (child.stdin.take(), &input) {
+            pipe::feed(stdin, input.clone());
+        }
+        relays.extend(
+            child
+                .stdout
+                .take()
+                .map(|out| relay(out, prefix.clone(), false)),
+        );
+        relays.extend(child.stderr.take().map(|err| relay(err, prefix, true)));
+        names.push(name);
+        children.push(child);
+    }
+    let Some(leader) = children.first().map(Child::id) else {
+        return Ok(ExitStatus::default());
+    };
+    signals::forward_to(leader, true, init);
+
+    let mut running = vec![true; children.len()];
+    let mut first_exit: Option<ExitStatus> = None;
+    let mut failure: Option<ExitStatus> = None;
+    while running.contains(&true) {
+        let (index, status) = wait_any(&mut children, &running, init).map_err(|e| {
+            io::Error::new(e.kind(), format!("Failed to wait for the commands: {}", e))
+        })?;
+        running[index] = false;
+        debug_eprintln(
+            debug,
+            format_args!("'{}' finished with status: {}", names[index], status),
+        );
+        if !status.success() && failure.is_none() {
+            failure = Some(status);
+        }
+        if stop_others && first_exit.is_none() {
+            first_exit = Some(status);
+            if running.contains(&true) {
+                debug_eprintln(
+                    debug,
+                    format_args!("'{}' exited, stopping the others.", names[index]),
+                );
+                // SAFETY: kill has no memory safety preconditions.
+                unsafe { libc::kill(-(leader as i32), libc::SIGTERM) };
+            }
+        }
+    }
+    signals::stop_forwarding();
+    for relay in relays {
+        let _ = relay.join();
+    }
+    Ok(first_exit.or(failure).unwrap_or_default())
+}
@@ -0,0 +1,176 @@
+//! Keys that only apply on some platforms or hosts, so one note can serve
+//! several machines: `KEY@linux=...`, `KEY@macos=...`, `KEY@host:NAME=...`.
+
+use std::collections::HashMap;
+use std::env::consts::{FAMILY, OS};
+
+/// The operating systems and families a condition may name, as Rust calls
+/// them. Any other suffix after an `@`, as in `ADMIN@example.com`, leaves
+/// the key as it is.
+const PLATFORMS: &[&str] = &[
+    "linux",
+    "macos",
+    "ios",
+    "android",
+    "freebsd",
+    "dragonfly",
+    "netbsd",
+    "openbsd",
+    "solaris",
+    "illumos",
+    "haiku",
+    "windows",
+    "unix",
+];
+
+/// Resolves conditional keys. A `KEY@CONDITION` variant whose condition
+/// holds replaces the plain `KEY`, a host beating an OS (`linux`, `macos`,
+/// ...) beating an OS family (`unix`, `windows`); the other variants are
+/// dropped. Conditions are case-insensitive.
+pub fn select(vars: HashMap<String, String>) -> HashMap<String, String> {
+    if !vars.keys().any(|key| split(key).is_some()) {
+        return vars;
+    }
+    let host = hostname();
+    let mut selected: HashMap<String, (u8, String)> = HashMap::new();
+    for (key, value) in vars {
+        let (name, rank) = match split(&key) {
+            None => (key, 0),
+            Some((name, condition)) => match rank(condition, host.as_deref()) {
+                Some(rank) => (name.to_string(), rank),
+                None => continue,
+            },
+        };
+        if selected.get(&name).is_none_or(|(best, _)| rank > *best) {
+            selected.insert(name, (rank, value));
+        }
+    }
+    selected
+        .into_iter()
+        .map(|(name, (_, value))| (name, value))
+        .collect()
+}
+
+/// Splits `KEY@CONDITION` into the key and a condition of a known form,
+/// an OS, an OS family or `host:NAME`.
+pub(super) fn split(key: &str) -> Option<(&str, &str)> {
+    let (name, condition) = key.rsplit_once('@')?;
+    let known = PLATFORMS
+        .iter()
+        .any(|platform| condition.eq_ignore_ascii_case(platform))
+        || condition
+            .get(..5)
+            .is_some_and(|p| p.eq_ignore_ascii_case("host:"))
+            && condition.len() > 5;
+    (known && !name.is_empty()).then_some((name, condition))
+}
+
+/// How specific `condition` is if it holds on this machine, `None` if not.
+fn rank(condition: &str, host: Option<&str>) -> Option<u8> {
+    if condition
+        .get(..5)
+        .is_some_and(|p| p.eq_ignore_ascii_case("host:"))
+    {
+        let wanted = &condition[5..];
+        // Match the full name or the part before the domain
+        let matches = host.is_some_and(|host| {
+            host.eq_ignore_ascii_case(wanted)
+                || host
+                    .split('.')
+                    .next()
+                    .is_some_and(|short| short.eq_ignore_ascii_case(wanted))
+        });
+        return matches.then_some(3);
+    }
+    if condition.eq_ignore_ascii_case(OS) {
+        Some(2)
+    } else if condition.eq_ignore_ascii_case(FAMILY) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// The name of this machine, if it can be determined.
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its full length.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8(buffer[..length].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// An OS that is not this one.
+    fn other_os() -> &'static str {
+        if OS == "linux" {
+            "windows"
+        } else {
+            "linux"
+        }
+    }
+
+    #[test]
+    fn only_known_conditions_split() {
+        assert_eq!(split("KEY@linux"), Some(("KEY", "linux")));
+        assert_eq!(split("KEY@MacOS"), Some(("KEY", "MacOS")));
+        assert_eq!(split("KEY@host:box"), Some(("KEY", "host:box")));
+        assert_eq!(split("A@b@unix"), Some(("A@b", "unix")));
+        assert_eq!(split("ADMIN@example.com"), None);
+        assert_eq!(split("KEY@host:"), None);
+        assert_eq!(split("@linux"), None);
+        assert_eq!(split("KEY"), None);
+    }
+
+    #[test]
+    fn ranks() {
+        assert_eq!(rank(OS, None), Some(2));
+        assert_eq!(rank(&OS.to_uppercase(), None), Some(2));
+        assert_eq!(rank(FAMILY, None), Some(1));
+        assert_eq!(rank(other_os(), None), None);
+        assert_eq!(rank("host:box", Some("box.example.com")), Some(3));
+        assert_eq!(
+            rank("host:BOX.example.com", Some("box.example.com")),
+            Some(3)
+        );
+        assert_eq!(rank("host:box", Some("other")), None);
+        assert_eq!(rank("host:box", None), None);
+    }
+
+    #[test]
+    fn most_specific_variant_wins() {
+        let os_key = format!("KEY@{}", OS);
+        let family_key = format!("KEY@{}", FAMILY);
+        let other_key = format!("KEY@{}", other_os());
+        let selected = select(vars(&[
+            ("KEY", "plain"),
+            (&family_key, "family"),
+            (&os_key, "os"),
+            (&other_key, "other"),
+        ]));
+        assert_eq!(selected, vars(&[("KEY", "os")]));
+
+        let selected = select(vars(&[("KEY", "plain"), (&other_key, "other")]));
+        assert_eq!(selected, vars(&[("KEY", "plain")]));
+    }
+
+    #[test]
+    fn unconditional_keys_with_at_are_kept() {
+        let os_key = format!("KEY@{}", OS);
+        let selected = select(vars(&[("ADMIN@example.com", "a"), (&os_key, "os")]));
+        assert_eq!(selected, vars(&[("ADMIN@example.com", "a"), ("KEY", "os")]));
+    }
+}
@@ -0,0 +1,450 @@
+//! The default `KEY=VALUE` format, compatible with dotenv files.
+
+use std::collections::HashMap;
+
+use crate::source::env_var_name;
+use crate::warn_eprintln;
+
+use super::ParseOptions;
+
+/// Parses a string containing lines in "KEY=VALUE" format into a HashMap.
+/// Values may be quoted the way dotenv files do it (see [`parse_value`]);
+/// quoted values and `KEY<<DELIMITER` heredocs may span several lines.
+/// INI-style `[section]` headers prefix the keys that follow with
+/// `SECTION_`, unless `options.section` selects a single section, whose keys
+/// are then kept unprefixed while those of other sections are dropped.
+/// Skips empty lines, comments (#), and malformed lines; the latter are
+/// reported through [`reject`], which fails in strict mode.
+pub fn parse(content: &str, options: &ParseOptions) -> Result<HashMap<String, String>, String> {
+    let debug_enabled = options.debug;
+    let mut env_vars = HashMap::new();
+    let mut section: Option<&str> = None;
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let number = index + 1;
+        // Skip empty lines or lines potentially starting with # (comments)
+        let trimmed_line = line.trim();
+        if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = section_header(trimmed_line) {
+            section = Some(name);
+            continue;
+        }
+        // Keep trailing whitespace for unquoted values if asked to
+        let trimmed_line = strip_export(if options.preserve_whitespace {
+            line.trim_start()
+        } else {
+            trimmed_line
+        });
+
+        let (key, pieces) = if let Some((key, delimiter)) = heredoc_start(trimmed_line) {
+            // A quoted delimiter ('EOF') disables interpolation, as in shells
+            let unquoted = delimiter
+                .strip_prefix('\'')
+                .and_then(|d| d.strip_suffix('\''));
+            let body = lines.by_ref().map(|(_, line)| line);
+            let Some(value) = read_heredoc(body, unquoted.unwrap_or(delimiter)) else {
+                reject(
+                    options,
+                    number,
+                    format!(
+                        "no closing '{}' line found for heredoc '{}'",
+                        delimiter, key
+                    ),
+                )?;
+                continue;
+            };
+            (key, vec![(value, unquoted.is_some())])
+        } else {
+            // Split the line at the first '='
+            let Some((raw_key, value)) = trimmed_line.split_once('=') else {
+                reject(
+                    options,
+                    number,
+                    format!("expected KEY=VALUE, got '{}'", line),
+                )?;
+                continue;
+            };
+
+            let key = raw_key.trim();
+            if key.is_empty() {
+                reject(options, number, format!("empty key in '{}'", line))?;
+                continue;
+            }
+            if options.strict && raw_key.contains(char::is_whitespace) {
+                reject(options, number, format!("whitespace in key '{}'", raw_key))?;
+            }
+
+            // An open quote continues on the following lines
+            let mut value = value.to_string();
+            let mut parsed = parse_value(&value, options.preserve_whitespace);
+            while parsed == Err(UNTERMINATED) {
+                let Some((_, next_line)) = lines.next() else {
+                    break;
+                };
+                value.push('\n');
+                value.push_str(next_line);
+                parsed = parse_value(&value, options.preserve_whitespace);
+            }
+
+            match parsed {
+                Ok(pieces) => (key, pieces),
+                Err(reason) => {
+                    reject(options, number, format!("{} in '{}'", reason, line))?;
+                    continue;
+                }
+            }
+        };
+
+        let key = match (section, options.section.as_deref()) {
+            (None, _) => key.to_string(),
+            (Some(current), Some(selected)) if current == selected => key.to_string(),
+            (Some(_), Some(_)) => continue,
+            (Some(current), None) => {
+                format!("{}_{}", env_var_name(current).to_uppercase(), key)
+            }
+        };
+
+        let strict = options.strict;
+        let value = pieces
+            .into_iter()
+            .map(|(piece, literal)| {
+                if literal || !(options.interpolate || options.expand_env) {
+                    Ok(piece)
+                } else if options.interpolate {
+                    interpolate(&piece, &env_vars, options.expand_env, strict, debug_enabled)
+                } else {
+                    // Only the parent environment with --no-interpolate --expand-env
+                    interpolate(&piece, &HashMap::new(), true, strict, debug_enabled)
+                }
+            })
+            .collect::<Result<String, String>>()
+            .map_err(|e| format!("line {}: {}", number, e))?;
+        env_vars.insert(key, value);
+    }
+    Ok(env_vars)
+}
+
+/// Reports a malformed line: an error in strict mode, otherwise a warning
+/// (shown with `--debug`) after which the line is skipped.
+fn reject(options: &ParseOptions, number: usize, problem: String) -> Result<(), String> {
+    if options.strict {
+        return Err(format!("line {}: {}", number, problem));
+    }
+    warn_eprintln(
+        options.debug,
+        format_args!("Skipping line {}: {}", number, problem),
+    );
+    Ok(())
+}
+
+/// Recognizes an INI-style `[section]` header, returning the section name.
+fn section_header(line: &str) -> Option<&str> {
+    let name = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Strips a leading `export` keyword, as found in shell rc files.
+fn strip_export(line: &str) -> &str {
+    match line.strip_prefix("export") {
+        Some(rest) if rest.starts_with([' ', '\t']) => rest.trim_start(),
+        _ => line,
+    }
+}
+
+/// Recognizes the start of a heredoc, `KEY<<DELIMITER`, returning the key
+/// and the delimiter.
+fn heredoc_start(line: &str) -> Option<(&str, &str)> {
+    let (key, delimiter) = line.split_once("<<")?;
+    let key = key.trim();
+    let delimiter = delimiter.trim();
+    let valid = !key.is_empty()
+        && !key.contains(['=', ' ', '\t'])
+        && !delimiter.is_empty()
+        && !delimiter.contains(char::is_whitespace);
+    valid.then_some((key, delimiter))
+}
+
+/// Collects the lines up to (not including) a line consisting of
+/// `delimiter`, joined by newlines. Returns `None` if the input ends first.
+fn read_heredoc<'a>(lines: impl Iterator<Item = &'a str>, delimiter: &str) -> Option<String> {
+    let mut body: Vec<&str> = Vec::new();
+    for line in lines {
+        if line.trim() == delimiter {
+            return Some(body.join("\n"));
+        }
+        body.push(line);
+    }
+    None
+}
+
+/// Why [`parse_value`] fails on a quote that is still open, so the value
+/// may continue on the next line.
+const UNTERMINATED: &str = "unterminated quoted value";
+
+/// Parses the part of a line after the first '='. Values wrapped in single
+/// or double quotes lose the quotes and keep everything inside verbatim,
+/// including whitespace, '=' and '#', except that the escapes `\n`, `\t`,
+/// `\\`, `\"` and `\$` are interpreted inside double quotes. Only whitespace or an
+/// inline comment may follow the closing quote, so that a stray quote
+/// (`'it's'`) fails rather than cutting the value short. Unquoted values
+/// end at a '#' preceded by whitespace (an inline comment) and are trimmed,
+/// unless `preserve` asks for the rest of the line verbatim. Returns the
+/// value in pieces, each with whether it is literal, i.e. exempt from
+/// interpolation: a single-quoted value, or an escaped `\$`.
+fn parse_value(raw: &str, preserve: bool) -> Result<Vec<(String, bool)>, &'static str> {
+    let untrimmed = raw;
+    let raw = raw.trim();
+    let quote = match raw.chars().next() {
+        Some(q @ ('"' | '\'')) => q,
+        _ if preserve => return Ok(vec![(untrimmed.to_string(), false)]),
+        _ => {
+            let value = strip_inline_comment(raw).trim_end().to_string();
+            return Ok(vec![(value, false)]);
+        }
+    };
+
+    let mut pieces = Vec::new();
+    let mut value = String::new();
+    let mut chars = raw[1..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == quote => {
+                if !strip_inline_comment(chars.as_str()).trim().is_empty() {
+                    return Err("text after the closing quote");
+                }
+                pieces.push((value, quote == '\''));
+                return Ok(pieces);
+            }
+            '\\' if quote == '"' => match chars.next() {
+                Some('"') => value.push('"'),
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('\\') => value.push('\\'),
+                Some('$') => {
+                    pieces.push((std::mem::take(&mut value), false));
+                    pieces.push(("$".to_string(), true));
+                }
+                Some(other) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    Err(UNTERMINATED)
+}
+
+/// Cuts an unquoted value at the first '#' that follows whitespace.
+fn strip_inline_comment(value: &str) -> &str {
+    value
+        .match_indices('#')
+        .find(|(i, _)| value[..*i].ends_with([' ', '\t']))
+        .map_or(value, |(i, _)| &value[..i])
+}
+
+/// Expands `${NAME}` references in `value`, looking `NAME` up among the keys
+/// in `parsed` and then in the parent environment. With `bare_names`, `$NAME`
+/// is expanded too and `$$` stands for a literal `$`. References to unknown
+/// names are kept as they are, with a warning, or fail with `strict`, so a
+/// secret that merely contains `${` is not mangled; a `${` without closing
+/// brace is kept as-is.
+pub(super) fn interpolate(
+    value: &str,
+    parsed: &HashMap<String, String>,
+    bare_names: bool,
+    strict: bool,
+    debug_enabled: bool,
+) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, remainder) = if let Some(braced) = after.strip_prefix('{') {
+            let Some(length) = braced.find('}') else {
+                result.push_str(&rest[start..]);
+                return Ok(result);
+            };
+            (&braced[..length], &braced[length + 1..])
+        } else if bare_names && after.starts_with('$') {
+            result.push('$');
+            rest = &after[1..];
+            continue;
+        } else {
+            let length = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if !bare_names || length == 0 || after.starts_with(|c: char| c.is_ascii_digit()) {
+                result.push('$');
+                rest = after;
+                continue;
+            }
+            (&after[..length], &after[length..])
+        };
+        match parsed.get(name) {
+            Some(found) => result.push_str(found),
+            None => match std::env::var(name) {
+                Ok(found) => result.push_str(&found),
+                Err(_) => {
+                    let reference = &rest[start..rest.len() - remainder.len()];
+                    if strict {
+                        return Err(format!("'{}' is not defined", reference));
+                    }
+                    warn_eprintln(
+                        debug_enabled,
+                        format_args!("'{}' is not defined, keeping it as is", reference),
+                    );
+                    result.push_str(reference);
+                }
+            },
+        }
+        rest = remainder;
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Format;
+    use crate::source::render_env_lines;
+
+    fn options(strict: bool) -> ParseOptions {
+        ParseOptions {
+            debug: false,
+            format: Format::Env,
+            interpolate: true,
+            expand_env: false,
+            preserve_whitespace: false,
+            strict,
+            section: None,
+            flatten_separator: "_".to_string(),
+            flatten_depth: None,
+        }
+    }
+
+    fn parse_lenient(content: &str) -> HashMap<String, String> {
+        parse(content, &options(false)).unwrap()
+    }
+
+    #[test]
+    fn unterminated_quote_is_skipped_or_fails_strictly() {
+        let content = "A=\"open\nB=1\n";
+        let vars = parse_lenient(content);
+        assert_eq!(vars.get("A"), None);
+        assert_eq!(vars.get("B"), None, "the open quote swallows the rest");
+        let error = parse(content, &options(true)).unwrap_err();
+        assert!(error.contains("line 1") && error.contains("unterminated"));
+    }
+
+    #[test]
+    fn quoted_value_spans_lines() {
+        let vars = parse_lenient("A=\"one\ntwo\"\nB=1\n");
+        assert_eq!(vars["A"], "one\ntwo");
+        assert_eq!(vars["B"], "1");
+    }
+
+    #[test]
+    fn text_after_closing_quote_is_rejected() {
+        for line in ["PW='it's'", "PW=\"x\"y", "PW='a'#b"] {
+            let vars = parse_lenient(&format!("{}\nB=1\n", line));
+            assert_eq!(vars.get("PW"), None, "{}", line);
+            assert_eq!(vars["B"], "1", "{}", line);
+            let error = parse(line, &options(true)).unwrap_err();
+            assert!(error.contains("after the closing quote"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn closing_quote_may_be_followed_by_a_comment() {
+        let vars = parse_lenient("A=\"x y\"   # comment\nB='z'  \n");
+        assert_eq!(vars["A"], "x y");
+        assert_eq!(vars["B"], "z");
+    }
+
+    #[test]
+    fn escaped_dollar_is_literal() {
+        let vars = parse_lenient("A=1\nB=\"\\${A} ${A} \\$\"\n");
+        assert_eq!(vars["B"], "${A} 1 $");
+    }
+
+    #[test]
+    fn heredoc_reads_up_to_its_delimiter() {
+        let vars = parse_lenient("A<<EOF\n  one\n\n#two\n  EOF\nB=1\n");
+        assert_eq!(vars["A"], "  one\n\n#two");
+        assert_eq!(vars["B"], "1");
+    }
+
+    #[test]
+    fn heredoc_with_quoted_delimiter_is_literal() {
+        let vars = parse_lenient("X=1\nA<<END\n${X}\nEND\nB<<'END'\n${X}\nEND\n");
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "${X}");
+    }
+
+    #[test]
+    fn heredoc_without_delimiter_is_rejected() {
+        assert!(parse_lenient("A<<EOF\nvalue\n").is_empty());
+        let error = parse("A<<EOF\nvalue\n", &options(true)).unwrap_err();
+        assert!(error.contains("no closing 'EOF'"), "{}", error);
+    }
+
+    #[test]
+    fn inline_comment_needs_whitespace_before_it() {
+        let vars = parse_lenient("A=1 # one\nB=a#b\nC=\"a # b\"\nD=x\t#tab\n");
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "a#b");
+        assert_eq!(vars["C"], "a # b");
+        assert_eq!(vars["D"], "x");
+    }
+
+    #[test]
+    fn unknown_reference_is_kept_or_fails_strictly() {
+        let content = "A=1\nPW=pa${RBWCHAIN_TEST_UNDEFINED}word${A}\n";
+        let vars = parse_lenient(content);
+        assert_eq!(vars["PW"], "pa${RBWCHAIN_TEST_UNDEFINED}word1");
+        let error = parse(content, &options(true)).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.contains("${RBWCHAIN_TEST_UNDEFINED}"), "{}", error);
+    }
+
+    #[test]
+    fn rendered_lines_parse_back_verbatim() {
+        let values = [
+            "plain",
+            "",
+            " padded ",
+            "tab\tinside",
+            "trailing\t",
+            "'single'",
+            "\"double\"",
+            "it's",
+            "a # not a comment",
+            "a\t# not a comment",
+            "pa${ss}word",
+            "$HOME and $$",
+            "line one\nline two\n",
+            "\nleading newline",
+            "EOF\ninside",
+            "ends with\nEOF",
+            "back\\slash\\n",
+            "equals=in=value",
+        ];
+        let pairs = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (format!("KEY_{}", i), value.to_string()));
+        let content = render_env_lines(pairs);
+        for strict in [false, true] {
+            let vars = parse(&content, &options(strict)).unwrap();
+            for (i, value) in values.iter().enumerate() {
+                assert_eq!(vars[&format!("KEY_{}", i)], *value, "in\n{}", content);
+            }
+        }
+    }
+}
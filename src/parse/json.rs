@@ -0,0 +1,27 @@
+//! Notes containing a JSON object.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde_json::Value;
+
+use super::{object_vars, ParseOptions};
+
+/// Whether `content` looks like a JSON object, for `--format auto`.
+pub fn detect(content: &str) -> bool {
+    content.trim_start().starts_with('{')
+        && serde_json::from_str::<Value>(content).is_ok_and(|v| v.is_object())
+}
+
+/// Turns the members of a JSON object into variables, see [`object_vars`].
+pub fn parse(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let value: Value = serde_json::from_str(content)
+        .map_err(|e| format!("Note content is not valid JSON: {}", e))?;
+    let Value::Object(members) = value else {
+        return Err("Note content is JSON, but not an object.".into());
+    };
+    Ok(object_vars(members, options))
+}
@@ -0,0 +1,233 @@
+//! Parsing of fetched note content into environment variables.
+//!
+//! Notes are `KEY=VALUE` lines by default ([`dotenv`]); structured formats
+//! are selected through the `--format` flag.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::source::env_var_name;
+
+mod conditional;
+mod dotenv;
+mod json;
+mod properties;
+mod toml;
+mod yaml;
+
+/// The syntax of a note's content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// JSON if the content is a JSON object, `KEY=VALUE` lines otherwise.
+    Auto,
+    /// `KEY=VALUE` lines, as in dotenv files.
+    Env,
+    /// A JSON object.
+    Json,
+    /// A YAML mapping.
+    Yaml,
+    /// A TOML document.
+    Toml,
+    /// Java `.properties` syntax.
+    Properties,
+}
+
+/// Options controlling how note content is turned into variables.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub debug: bool,
+    pub format: Format,
+    /// Expand `${NAME}` references in `KEY=VALUE` values.
+    pub interpolate: bool,
+    /// Also expand bare `$NAME` references from the parent environment, in
+    /// the values of every format.
+    pub expand_env: bool,
+    /// Keep unquoted `KEY=VALUE` values verbatim instead of trimming them
+    /// and stripping inline comments.
+    pub preserve_whitespace: bool,
+    /// Fail on malformed `KEY=VALUE` lines instead of skipping them.
+    pub strict: bool,
+    /// Only read this `[section]` of `KEY=VALUE` notes.
+    pub section: Option<String>,
+    /// Joins the names of nested keys in structured notes.
+    pub flatten_separator: String,
+    /// How many levels of nested objects are flattened (unlimited if `None`).
+    pub flatten_depth: Option<usize>,
+}
+
+/// Parses note content into variables according to `options.format`, then
+/// picks the variants of `KEY@CONDITION` keys that apply to this machine.
+pub fn parse_env_vars(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    Ok(conditional::select(parse_content(content, options)?))
+}
+
+fn parse_content(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let content = normalize(content);
+    let content = content.as_ref();
+    let vars = match options.format {
+        Format::Env => return Ok(dotenv::parse(content, options)?),
+        Format::Json => json::parse(content, options)?,
+        Format::Yaml => yaml::parse(content, options)?,
+        Format::Toml => toml::parse(content, options)?,
+        Format::Properties => properties::parse(content)?,
+        Format::Auto if json::detect(content) => json::parse(content, options)?,
+        Format::Auto => return Ok(dotenv::parse(content, options)?),
+    };
+    if !options.expand_env {
+        return Ok(vars);
+    }
+    // Structured formats have no quoting to opt out with, so expand everything
+    let no_keys = HashMap::new();
+    vars.into_iter()
+        .map(|(key, value)| {
+            let value = dotenv::interpolate(&value, &no_keys, true, options.strict, options.debug)
+                .map_err(|e| format!("{}: {}", key, e))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Whether `name` is a portable environment variable name as defined by
+/// POSIX: ASCII letters, digits and underscores, not starting with a digit.
+pub fn is_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Strips a leading UTF-8 byte order mark and turns Windows (`\r\n`) line
+/// endings into `\n`, so notes edited on Windows or pasted from some editors
+/// do not leave a stray `\r` at the end of values.
+fn normalize(content: &str) -> Cow<'_, str> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    if content.contains('\r') {
+        let mut normalized = content.replace("\r\n", "\n");
+        if normalized.ends_with('\r') {
+            normalized.pop(); // Final line without its '\n'
+        }
+        Cow::Owned(normalized)
+    } else {
+        Cow::Borrowed(content)
+    }
+}
+
+/// Turns the members of a structured note into variables. String values are
+/// used as-is, other scalars and arrays in their JSON serialization. Nested
+/// objects are expanded into `PARENT<SEPARATOR>CHILD` variables down to
+/// `options.flatten_depth` levels; deeper ones are serialized too. Every
+/// part of a name is normalized by [`name_part`], whatever its depth.
+fn object_vars(members: Map<String, Value>, options: &ParseOptions) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    for (key, value) in members {
+        insert_value(&mut vars, name_part(&key), value, 0, options);
+    }
+    vars
+}
+
+/// Maps a key of a structured note to a valid variable name with
+/// [`env_var_name`], keeping its case and any `@CONDITION` suffix. The
+/// case is left to `--upcase-keys` and `--downcase-keys`.
+fn name_part(key: &str) -> String {
+    match conditional::split(key) {
+        Some((name, condition)) => format!("{}@{}", env_var_name(name), condition),
+        None => env_var_name(key),
+    }
+}
+
+fn insert_value(
+    vars: &mut HashMap<String, String>,
+    name: String,
+    value: Value,
+    depth: usize,
+    options: &ParseOptions,
+) {
+    match value {
+        Value::Object(members) if options.flatten_depth.is_none_or(|max| depth < max) => {
+            for (key, value) in members {
+                let nested = format!("{}{}{}", name, options.flatten_separator, name_part(&key));
+                insert_value(vars, nested, value, depth + 1, options);
+            }
+        }
+        Value::String(s) => {
+            vars.insert(name, s);
+        }
+        other => {
+            vars.insert(name, other.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(format: Format) -> ParseOptions {
+        ParseOptions {
+            debug: false,
+            format,
+            interpolate: true,
+            expand_env: false,
+            preserve_whitespace: false,
+            strict: true,
+            section: None,
+            flatten_separator: "_".to_string(),
+            flatten_depth: None,
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn nested_names_are_normalized_like_top_level_ones() {
+        let content = r#"{"db": {"host": "h", "db-port": 5432, "Tls": {"on": true}},
+            "db_user": "u", "api.key": "k", "2fa": [1]}"#;
+        assert_eq!(
+            parse_content(content, &options(Format::Json)).unwrap(),
+            vars(&[
+                ("db_host", "h"),
+                ("db_db_port", "5432"),
+                ("db_Tls_on", "true"),
+                ("db_user", "u"),
+                ("api_key", "k"),
+                ("_2fa", "[1]"),
+            ])
+        );
+    }
+
+    #[test]
+    fn flatten_separator_and_depth() {
+        let mut options = options(Format::Yaml);
+        options.flatten_separator = "__".to_string();
+        options.flatten_depth = Some(1);
+        let content = "app:\n  db:\n    host: h\n  name: x\n";
+        assert_eq!(
+            parse_content(content, &options).unwrap(),
+            vars(&[("app__db", r#"{"host":"h"}"#), ("app__name", "x")])
+        );
+    }
+
+    #[test]
+    fn conditions_survive_flattening() {
+        let key = format!("host@{}", std::env::consts::OS);
+        let content = format!(r#"{{"db": {{"host": "plain", "{}": "os"}}}}"#, key);
+        assert_eq!(
+            parse_env_vars(&content, &options(Format::Json)).unwrap(),
+            vars(&[("db_host", "os")])
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Notes in Java `.properties` syntax.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Parses `.properties` content: `key=value`, `key: value` or `key value`
+/// pairs, `#`/`!` comments, backslash line continuations and `\uXXXX`
+/// escapes. Keys are used as-is.
+pub fn parse(content: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut vars = HashMap::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with(['#', '!']) {
+            continue;
+        }
+
+        // Join continuation lines, dropping their leading whitespace
+        let mut logical = line.to_string();
+        while ends_with_continuation(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some(next) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_pair(&logical);
+        vars.insert(unescape(key)?, unescape(value)?);
+    }
+    Ok(vars)
+}
+
+/// Whether `line` ends in an odd number of backslashes.
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Splits a logical line at the first unescaped '=', ':' or whitespace,
+/// skipping the whitespace around the separator.
+fn split_pair(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut key_end = line.len();
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            key_end = i;
+            break;
+        }
+    }
+
+    let rest = line[key_end..].trim_start();
+    let rest = rest.strip_prefix(['=', ':']).unwrap_or(rest).trim_start();
+    (&line[..key_end], rest)
+}
+
+/// Resolves the escapes of a key or value: `\t`, `\n`, `\r`, `\f`,
+/// `\uXXXX`, and `\` before any other character standing for itself.
+/// Characters beyond `\uFFFF` are written as a UTF-16 surrogate pair, e.g.
+/// `\uD83D\uDE00`, as Java does.
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('f') => result.push('\x0c'),
+            Some('u') => {
+                let mut code = code_unit(&mut chars);
+                if let Some(high @ 0xD800..=0xDBFF) = code {
+                    let mut ahead = chars.clone();
+                    let low = match (ahead.next(), ahead.next()) {
+                        (Some('\\'), Some('u')) => code_unit(&mut ahead),
+                        _ => None,
+                    };
+                    code = match low {
+                        Some(low @ 0xDC00..=0xDFFF) => {
+                            chars = ahead;
+                            Some(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                        }
+                        _ => None,
+                    };
+                }
+                let code = code
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid unicode escape in '{}'.", text))?;
+                result.push(code);
+            }
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the four hex digits of a `\uXXXX` escape.
+fn code_unit(chars: &mut std::str::Chars) -> Option<u32> {
+    let hex: String = chars.by_ref().take(4).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators() {
+        let vars = parse("a=1\nb: 2\nc 3\nd = 4\ne\t:\t5\nf=x=y\ng\n").unwrap();
+        assert_eq!(vars["a"], "1");
+        assert_eq!(vars["b"], "2");
+        assert_eq!(vars["c"], "3");
+        assert_eq!(vars["d"], "4");
+        assert_eq!(vars["e"], "5");
+        assert_eq!(vars["f"], "x=y");
+        assert_eq!(vars["g"], "");
+    }
+
+    #[test]
+    fn escaped_separators_belong_to_the_key() {
+        let vars = parse("a\\=b\\:c\\ d=1\n").unwrap();
+        assert_eq!(vars["a=b:c d"], "1");
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let vars = parse("# one\n  ! two\na=1 # not a comment\n").unwrap();
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars["a"], "1 # not a comment");
+    }
+
+    #[test]
+    fn continuation_lines_are_joined() {
+        let vars = parse("list=a,\\\n    b,\\\n    c\nnext=1\n").unwrap();
+        assert_eq!(vars["list"], "a,b,c");
+        assert_eq!(vars["next"], "1");
+    }
+
+    #[test]
+    fn escaped_backslash_does_not_continue() {
+        let vars = parse("path=C:\\\\\nnext=1\n").unwrap();
+        assert_eq!(vars["path"], "C:\\");
+        assert_eq!(vars["next"], "1");
+    }
+
+    #[test]
+    fn continuation_at_end_of_input() {
+        let vars = parse("a=1\\").unwrap();
+        assert_eq!(vars["a"], "1");
+    }
+
+    #[test]
+    fn escapes() {
+        let vars = parse("a=tab\\tnl\\nx\\y\nb=caf\\u00e9\nc=\\uD83D\\uDE00!\n").unwrap();
+        assert_eq!(vars["a"], "tab\tnl\nxy");
+        assert_eq!(vars["b"], "café");
+        assert_eq!(vars["c"], "😀!");
+    }
+
+    #[test]
+    fn invalid_unicode_escapes_fail() {
+        for value in [
+            "\\u+abc", "\\u12", "\\uzzzz", "\\uD83D", "\\uD83Dx", "\\uDE00",
+        ] {
+            assert!(parse(&format!("a={}\n", value)).is_err(), "{}", value);
+        }
+    }
+}
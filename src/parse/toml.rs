@@ -0,0 +1,42 @@
+//! Notes containing a TOML document.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde_json::{Map, Value};
+use toml::Table;
+
+use super::{object_vars, ParseOptions};
+
+/// Turns the keys of a TOML document into variables, `[table]` sections
+/// becoming `table_key`, see [`object_vars`].
+pub fn parse(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let table: Table = content
+        .parse()
+        .map_err(|e| format!("Note content is not valid TOML: {}", e))?;
+    Ok(object_vars(to_json_members(table), options))
+}
+
+/// Converts a TOML table into JSON object members, so values are rendered
+/// the same way as for JSON notes. Dates and times become strings.
+fn to_json_members(table: Table) -> Map<String, Value> {
+    table
+        .into_iter()
+        .map(|(key, value)| (key, to_json(value)))
+        .collect()
+}
+
+fn to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(to_json).collect()),
+        toml::Value::Table(table) => Value::Object(to_json_members(table)),
+    }
+}
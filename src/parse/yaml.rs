@@ -0,0 +1,25 @@
+//! Notes containing a YAML mapping.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde_json::Value;
+
+use super::{object_vars, ParseOptions};
+
+/// Turns the entries of a YAML mapping into variables, flattening nested
+/// mappings, see [`object_vars`].
+pub fn parse(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    // Going through serde_json's data model keeps value rendering identical
+    // to JSON notes; it requires string keys, as env var names do anyway
+    let value: Value = serde_yaml::from_str(content)
+        .map_err(|e| format!("Note content is not valid YAML: {}", e))?;
+    match value {
+        Value::Object(members) => Ok(object_vars(members, options)),
+        Value::Null => Ok(HashMap::new()), // Empty document
+        _ => Err("Note content is YAML, but not a mapping.".into()),
+    }
+}
@@ -0,0 +1,202 @@
+//! Permissions, group ownership and storage of the files secrets are
+//! written to.
+
+use std::ffi::{CStr, CString};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+
+/// Parses an octal `--file-mode` such as `0640`, or a `--umask` such as `077`.
+pub fn parse_mode(spec: &str) -> Result<u32, String> {
+    u32::from_str_radix(spec, 8)
+        .ok()
+        .filter(|&mode| mode <= 0o777)
+        .ok_or_else(|| format!("expected octal permissions like 0640, got '{}'", spec))
+}
+
+/// Resolves a `--file-group` name or numeric id to a group id.
+pub fn parse_group(spec: &str) -> Result<u32, String> {
+    if let Ok(gid) = spec.parse::<u32>() {
+        return Ok(gid);
+    }
+    let name = CString::new(spec).map_err(|_| format!("invalid group name '{}'", spec))?;
+    // SAFETY: `name` is a valid C string; the returned entry is only read
+    // before any other call that could overwrite it.
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("unknown group '{}'", spec));
+    }
+    // SAFETY: checked for null above.
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+/// An account the command runs as (`--user`).
+#[derive(Debug, Clone)]
+pub struct User {
+    pub uid: u32,
+    /// The primary group, unknown for a numeric id without a passwd entry.
+    pub gid: Option<u32>,
+    /// The supplementary groups the account is a member of.
+    pub groups: Vec<u32>,
+}
+
+/// Resolves a `--user` name or numeric id, along with its groups.
+pub fn parse_user(spec: &str) -> Result<User, String> {
+    // SAFETY: The returned entry is only read before any other call that
+    // could overwrite it.
+    let entry = match spec.parse::<u32>() {
+        Ok(uid) => unsafe { libc::getpwuid(uid) },
+        Err(_) => {
+            let name = CString::new(spec).map_err(|_| format!("invalid user name '{}'", spec))?;
+            // SAFETY: `name` is a valid C string.
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        }
+    };
+    if entry.is_null() {
+        // Containers commonly run as ids without an account
+        return match spec.parse::<u32>() {
+            Ok(uid) => Ok(User {
+                uid,
+                gid: None,
+                groups: Vec::new(),
+            }),
+            Err(_) => Err(format!("unknown user '{}'", spec)),
+        };
+    }
+    // SAFETY: checked for null above; pw_name is a valid C string.
+    let (uid, gid, name) = unsafe {
+        (
+            (*entry).pw_uid,
+            (*entry).pw_gid,
+            CStr::from_ptr((*entry).pw_name),
+        )
+    };
+    let name = name.to_owned();
+
+    let mut groups: Vec<libc::gid_t> = vec![0; 32];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        // SAFETY: `groups` has room for `count` entries; getgrouplist
+        // updates `count` to the number needed.
+        let found = unsafe {
+            libc::getgrouplist(
+                name.as_ptr(),
+                gid as _,
+                groups.as_mut_ptr() as _,
+                &mut count,
+            )
+        };
+        if found >= 0 {
+            groups.truncate(count as usize);
+            break;
+        }
+        groups.resize(count.max(groups.len() as libc::c_int * 2) as usize, 0);
+    }
+    Ok(User {
+        uid,
+        gid: Some(gid),
+        groups,
+    })
+}
+
+/// Sets `mode` on `file` and, if given, hands it to user `uid` and group
+/// `gid`, through its descriptor so the path cannot be swapped in between.
+pub fn apply(file: &File, mode: u32, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    let fd = file.as_raw_fd();
+    if uid.is_some() || gid.is_some() {
+        // SAFETY: `fd` is open for the lifetime of `file`; -1 keeps the
+        // current owner or group.
+        let (uid, gid) = (
+            uid.unwrap_or(libc::uid_t::MAX),
+            gid.unwrap_or(libc::gid_t::MAX),
+        );
+        if unsafe { libc::fchown(fd, uid, gid) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // SAFETY: as above. Applied after fchown, which may clear setgid bits.
+    if unsafe { libc::fchmod(fd, mode as libc::mode_t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Whether `dir` is on a memory-backed filesystem (tmpfs or ramfs), so
+/// files written there never reach persistent storage. Only Linux can tell;
+/// elsewhere this is always `false`.
+pub fn is_memory_backed(dir: &Path) -> io::Result<bool> {
+    if !cfg!(target_os = "linux") {
+        return Ok(false);
+    }
+    const TMPFS_MAGIC: i64 = 0x0102_1994;
+    const RAMFS_MAGIC: i64 = 0x8584_58f6;
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: an all-zero statfs is a valid value for statfs to overwrite.
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid C string and `stats` a valid statfs buffer.
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)] // f_type's width differs by platform
+    let kind = stats.f_type as i64;
+    Ok(kind == TMPFS_MAGIC || kind == RAMFS_MAGIC)
+}
+
+/// Overwrites the content of `file` with zeros and flushes it to storage, so
+/// the secret does not linger in freed blocks once the file is deleted.
+pub fn wipe(file: &File) -> io::Result<()> {
+    let length = file.metadata()?.len();
+    let zeros = [0u8; 8192];
+    let mut offset = 0;
+    while offset < length {
+        let chunk = zeros.len().min((length - offset) as usize);
+        file.write_all_at(&zeros[..chunk], offset)?;
+        offset += chunk as u64;
+    }
+    file.sync_all()
+}
+
+/// Like [`wipe`], for the file at `path`. Symbolic links are not followed.
+pub fn wipe_path(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    wipe(&file)
+}
+
+/// Creates an anonymous in-memory file (`memfd_create`) holding `content`,
+/// which exists on no filesystem at all. The descriptor is inherited by
+/// child processes. Only available on Linux.
+pub fn memfd(name: &str, content: &[u8]) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::io::Write;
+        use std::os::fd::FromRawFd;
+
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: `name` is a valid C string. No MFD_CLOEXEC: the command
+        // must inherit the descriptor.
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just created and is owned by nothing else.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(content)?;
+        Ok(file)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (name, content);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memfd is only available on Linux",
+        ))
+    }
+}
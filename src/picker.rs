@@ -0,0 +1,198 @@
+//! A small built-in fuzzy finder used by `--pick` to choose a note
+//! interactively on the terminal.
+
+use std::cmp::Reverse;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+
+/// How many matches are shown below the prompt.
+const MAX_VISIBLE: usize = 10;
+/// How long to wait for the rest of an escape sequence after ESC.
+const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+// --- Matching ---
+
+/// Scores `candidate` against `query` as a case-insensitive subsequence
+/// match. Consecutive characters and matches at word starts score higher.
+/// Returns `None` if `query` is not a subsequence of `candidate`.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for wanted in query.chars().map(|c| c.to_ascii_lowercase()) {
+        let found =
+            (position..candidate.len()).find(|&i| candidate[i].to_ascii_lowercase() == wanted)?;
+        score += 1;
+        if previous_match.is_some_and(|p| p + 1 == found) {
+            score += 5; // Consecutive run
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3; // Start of a word
+        }
+        score -= (found - position) as i64; // Gaps cost a little
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Returns the candidates matching `query`, best first.
+pub fn rank<'a>(query: &str, candidates: &'a [String]) -> Vec<&'a String> {
+    let mut matches: Vec<(i64, &String)> = candidates
+        .iter()
+        .filter_map(|c| fuzzy_score(query, c).map(|score| (score, c)))
+        .collect();
+    // Stable sort keeps the listing order for equal scores
+    matches.sort_by_key(|(score, _)| Reverse(*score));
+    matches.into_iter().map(|(_, c)| c).collect()
+}
+
+// --- Terminal Handling ---
+
+/// Puts the terminal into raw mode and restores it when dropped.
+struct RawMode {
+    fd: i32,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(tty: &File) -> io::Result<RawMode> {
+        let fd = tty.as_raw_fd();
+        // SAFETY: termios is plain data and fd is a valid open terminal.
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { fd, original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in `enable` on the same fd.
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// A decoded keypress.
+enum Key {
+    Char(char),
+    Backspace,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    Other,
+}
+
+/// Waits up to `timeout_ms` for more input on `tty`.
+fn input_pending(tty: &File, timeout_ms: i32) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: tty.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: pollfd points to a single valid, initialized entry.
+    unsafe { libc::poll(&mut pollfd, 1, timeout_ms) > 0 }
+}
+
+fn read_key(tty: &mut File) -> io::Result<Key> {
+    let mut byte = [0u8; 1];
+    tty.read_exact(&mut byte)?;
+    Ok(match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        0x03 | 0x04 => Key::Cancel, // Ctrl-C, Ctrl-D
+        0x7f | 0x08 => Key::Backspace,
+        0x10 => Key::Up,   // Ctrl-P
+        0x0e => Key::Down, // Ctrl-N
+        0x1b => {
+            // A lone ESC cancels; ESC [ A/B are the arrow keys
+            if !input_pending(tty, ESCAPE_TIMEOUT_MS) {
+                return Ok(Key::Cancel);
+            }
+            let mut sequence = [0u8; 2];
+            match tty.read(&mut sequence)? {
+                2 if sequence == *b"[A" => Key::Up,
+                2 if sequence == *b"[B" => Key::Down,
+                _ => Key::Other,
+            }
+        }
+        b if b.is_ascii_graphic() || b == b' ' => Key::Char(b as char),
+        _ => Key::Other,
+    })
+}
+
+/// Redraws the prompt and the visible matches, leaving the cursor on the
+/// prompt line.
+fn draw(
+    tty: &mut File,
+    query: &str,
+    matches: &[&String],
+    selected: usize,
+    total: usize,
+) -> io::Result<()> {
+    let visible = &matches[..matches.len().min(MAX_VISIBLE)];
+    write!(tty, "\r\x1b[J> {}", query)?;
+    for (i, candidate) in visible.iter().enumerate() {
+        let marker = if i == selected { '>' } else { ' ' };
+        write!(tty, "\r\n{} {}", marker, candidate)?;
+    }
+    write!(tty, "\r\n  {}/{}", matches.len(), total)?;
+    // Back up to the prompt line, after the query
+    write!(
+        tty,
+        "\x1b[{}A\r\x1b[{}C",
+        visible.len() + 1,
+        query.len() + 2
+    )?;
+    tty.flush()
+}
+
+/// Lets the user pick one of `candidates` on the controlling terminal.
+/// Returns `Ok(None)` if the user cancelled.
+pub fn pick(candidates: &[String]) -> io::Result<Option<String>> {
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let raw_mode = RawMode::enable(&tty)?;
+
+    let mut query = String::new();
+    let mut selected = 0;
+    let choice = loop {
+        let matches = rank(&query, candidates);
+        selected = selected.min(matches.len().min(MAX_VISIBLE).saturating_sub(1));
+        draw(&mut tty, &query, &matches, selected, candidates.len())?;
+
+        match read_key(&mut tty)? {
+            Key::Char(c) => {
+                query.push(c);
+                selected = 0;
+            }
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected += 1,
+            Key::Enter => break matches.get(selected).map(|c| c.to_string()),
+            Key::Cancel => break None,
+            Key::Other => {}
+        }
+    };
+
+    // Clear the picker before handing the terminal to the child
+    write!(tty, "\r\x1b[J")?;
+    tty.flush()?;
+    drop(raw_mode);
+    Ok(choice)
+}
@@ -0,0 +1,71 @@
+//! Passing secrets to the command through pipes rather than files.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::thread;
+
+/// Creates a pipe carrying `content`, whose read end is handed to the
+/// command with [`pass_to`]. The content is written from a background
+/// thread, since a pipe holds only a limited amount of data until the
+/// command reads it. The write end is closed once everything is written, so
+/// the command sees end-of-file.
+pub fn inherited(content: Vec<u8>) -> io::Result<OwnedFd> {
+    // Neither end survives exec by default: hooks and other commands must
+    // not drain the secret, and the command would hold the write end open
+    // itself and never see end-of-file. The flag is set as the pipe is
+    // created, so a process started meanwhile by another thread (--watch)
+    // cannot inherit it either.
+    let (read_end, write_end) = cloexec_pipe()?;
+    feed(File::from(write_end), content);
+    Ok(read_end)
+}
+
+/// Creates a pipe whose ends are both close-on-exec, returning the read
+/// and the write end.
+fn cloexec_pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors pipe2() returns.
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    // Apple systems have no pipe2(), so the flag is set right afterwards.
+    // SAFETY: `fds` has room for the two descriptors pipe() returns.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let result = unsafe { libc::pipe(fds.as_mut_ptr()) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: Both descriptors were just created and are owned by nothing else.
+    let ends = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    for fd in fds {
+        // SAFETY: `fd` is a valid descriptor owned by `ends`.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(ends)
+}
+
+/// Lets `command`, and no other process, inherit the descriptor `fd`.
+pub fn pass_to(command: &mut Command, fd: RawFd) {
+    // SAFETY: The hook only makes an async-signal-safe system call.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::fcntl(fd, libc::F_SETFD, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Writes `content` to `writer` from a background thread, then closes it.
+/// The command may exit without reading everything; that is not an error.
+pub fn feed(mut writer: impl Write + Send + 'static, content: Vec<u8>) {
+    thread::spawn(move || {
+        let _ = writer.write_all(&content);
+    });
+}
@@ -0,0 +1,92 @@
+//! Reading of Procfiles (`--procfile`): `name: command` lines naming the
+//! processes of an application, as used by foreman and Heroku.
+
+/// Whether `name` can name a process: letters, digits, `_` and `-`.
+fn is_process_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// Parses `content` into `(name, command)` pairs in file order. Blank lines
+/// and lines starting with `#` are skipped.
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut processes: Vec<(String, String)> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, command)) = line.split_once(':') else {
+            return Err(format!(
+                "Line {}: expected 'name: command', got '{}'",
+                number + 1,
+                line
+            ));
+        };
+        let (name, command) = (name.trim(), command.trim());
+        if !is_process_name(name) {
+            return Err(format!(
+                "Line {}: invalid process name '{}' (use letters, digits, '_' and '-')",
+                number + 1,
+                name
+            ));
+        }
+        if command.is_empty() {
+            return Err(format!("Line {}: '{}' has no command", number + 1, name));
+        }
+        if processes.iter().any(|(other, _)| other == name) {
+            return Err(format!(
+                "Line {}: the process '{}' is defined twice",
+                number + 1,
+                name
+            ));
+        }
+        processes.push((name.to_string(), command.to_string()));
+    }
+    if processes.is_empty() {
+        return Err("The Procfile defines no processes.".to_string());
+    }
+    Ok(processes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processes_in_file_order() {
+        let content = "# dev\n\nweb: bundle exec puma -p $PORT\n  worker-1 :  sidekiq  \nurl: curl http://x\n";
+        assert_eq!(
+            parse(content).unwrap(),
+            [
+                ("web".to_string(), "bundle exec puma -p $PORT".to_string()),
+                ("worker-1".to_string(), "sidekiq".to_string()),
+                ("url".to_string(), "curl http://x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        for (content, expected) in [
+            ("web: a\nworker\n", "Line 2: expected"),
+            ("web server: a\n", "Line 1: invalid process name"),
+            (": a\n", "Line 1: invalid process name"),
+            ("\nweb:\n", "Line 2: 'web' has no command"),
+            (
+                "web: a\nweb: b\n",
+                "Line 2: the process 'web' is defined twice",
+            ),
+        ] {
+            let error = parse(content).unwrap_err();
+            assert!(error.starts_with(expected), "{}", error);
+        }
+    }
+
+    #[test]
+    fn empty_procfile_fails() {
+        assert!(parse("# nothing\n\n").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Rewriting the -f files while the command runs (`--reload-signal`,
+//! `--refresh-file`), so that it can pick up changed secrets without being
+//! restarted.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use tempfile::Builder;
+
+use crate::perms;
+
+/// Where the command finds an -f file.
+pub enum Target {
+    /// A file on a filesystem, replaced as a whole.
+    Path(PathBuf),
+    /// An in-memory file (`--memfd`) the command inherited, rewritten in
+    /// place as it has no path to replace.
+    Descriptor(File),
+}
+
+/// How the rewritten files are owned, like the first ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Ownership {
+    pub mode: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Replaces the content of `target` with `content`. A file on a filesystem
+/// is replaced atomically, by renaming a new file over it, so a reader
+/// never sees half of it; the old one is wiped afterwards. An in-memory
+/// file is overwritten before it is cut to the new length, so it is never
+/// shorter than the new content, although a reader may catch the end of a
+/// longer old one.
+pub fn rewrite(target: &Target, content: &[u8], ownership: Ownership) -> io::Result<()> {
+    match target {
+        Target::Path(path) => replace(path, content, ownership),
+        Target::Descriptor(file) => {
+            file.write_all_at(content, 0)?;
+            file.set_len(content.len() as u64)
+        }
+    }
+}
+
+fn replace(path: &Path, content: &[u8], ownership: Ownership) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // Opened before the rename, so the old content can still be wiped
+    let old = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .ok();
+    let mut new = Builder::new().prefix(".rbwchain").tempfile_in(directory)?;
+    new.write_all(content)?;
+    new.flush()?;
+    perms::apply(new.as_file(), ownership.mode, ownership.uid, ownership.gid)?;
+    new.persist(path).map_err(|e| e.error)?;
+    if let Some(old) = old {
+        let _ = perms::wipe(&old);
+    }
+    Ok(())
+}
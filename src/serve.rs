@@ -0,0 +1,150 @@
+//! Serving the parsed variables over a unix socket (`--serve`), so that the
+//! command asks for each value at the moment it needs it instead of holding
+//! all of them in its environment.
+//!
+//! Every connection carries one request, a line of text:
+//!
+//! - `GET KEY` is answered with `OK`, a newline and the value;
+//! - `LIST` is answered with `OK`, a newline and the keys, one per line;
+//! - anything else, or an unknown key, with `ERR` and a message.
+//!
+//! The connection is closed after the answer, so a shell can ask with
+//! `echo 'GET DB_PASSWORD' | nc -U "$RBWCHAIN_SOCKET" | tail -n +2`.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use tempfile::{Builder, TempDir};
+
+use crate::debug_eprintln;
+
+/// The longest request line read, far more than any key needs.
+const MAX_REQUEST: u64 = 4096;
+
+/// Listens on a socket in a new private directory under `location` and
+/// answers requests for `vars` until rbwchain exits. Only processes of this
+/// user, or of `owner` (who the command runs as) are answered. Returns the
+/// directory, deleted with the socket when dropped, and the socket's path.
+pub fn start(
+    location: &Path,
+    owner: Option<u32>,
+    vars: HashMap<OsString, OsString>,
+    debug: bool,
+) -> io::Result<(TempDir, PathBuf)> {
+    let dir = Builder::new()
+        .permissions(Permissions::from_mode(0o700))
+        .tempdir_in(location)?;
+    let path = dir.path().join("socket");
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+    if owner.is_some() {
+        std::os::unix::fs::chown(dir.path(), owner, None)?;
+        std::os::unix::fs::chown(&path, owner, None)?;
+    }
+
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let mut allowed = vec![unsafe { libc::geteuid() }];
+    allowed.extend(owner);
+    let vars = Arc::new(vars);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let (vars, allowed) = (Arc::clone(&vars), allowed.clone());
+            // A client that never sends its request holds up only itself
+            thread::spawn(move || {
+                if let Err(e) = answer(stream, &vars, &allowed, debug) {
+                    debug_eprintln(debug, format_args!("--serve: {}", e));
+                }
+            });
+        }
+    });
+    Ok((dir, path))
+}
+
+/// The user id of the process at the other end of `stream`.
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    use std::os::fd::AsRawFd;
+
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: `credentials` is a valid place of the given size for
+        // getsockopt to write to.
+        unsafe {
+            let mut credentials: libc::ucred = std::mem::zeroed();
+            let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+            let result = libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut length,
+            );
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(credentials.uid)
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: `uid` and `gid` are valid places for getpeereid to write to.
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+}
+
+/// Reads the request of one connection and writes the answer.
+fn answer(
+    mut stream: UnixStream,
+    vars: &HashMap<OsString, OsString>,
+    allowed: &[u32],
+    debug: bool,
+) -> io::Result<()> {
+    let uid = peer_uid(&stream)?;
+    if !allowed.contains(&uid) {
+        stream.write_all(b"ERR permission denied\n")?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("refused a request of user {}", uid),
+        ));
+    }
+
+    let mut request = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST)).read_line(&mut request)?;
+    let request = request.trim_end_matches(['\r', '\n']);
+    debug_eprintln(debug, format_args!("--serve: '{}'", request));
+    let mut reply = Vec::new();
+    match request.split_once(' ') {
+        Some(("GET", key)) => match vars.get(&OsString::from(key)) {
+            Some(value) => {
+                reply.extend_from_slice(b"OK\n");
+                reply.extend_from_slice(value.as_bytes());
+            }
+            None => reply.extend_from_slice(format!("ERR unknown key '{}'\n", key).as_bytes()),
+        },
+        None if request == "LIST" => {
+            let mut keys: Vec<_> = vars.keys().collect();
+            keys.sort();
+            reply.extend_from_slice(b"OK\n");
+            for key in keys {
+                reply.extend_from_slice(key.as_bytes());
+                reply.push(b'\n');
+            }
+        }
+        _ => reply.extend_from_slice(b"ERR expected 'GET KEY' or 'LIST'\n"),
+    }
+    stream.write_all(&reply)
+}
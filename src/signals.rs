@@ -0,0 +1,154 @@
+//! Forwarding of termination signals to the command, so that rbwchain
+//! outlives it and can clean up the files it wrote.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// The process id of the running command, 0 before it started.
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Whether the command leads its own process group.
+static OWN_GROUP: AtomicBool = AtomicBool::new(false);
+
+/// The signal that asked rbwchain to stop, 0 if none did: SIGINT, SIGTERM
+/// or SIGQUIT, or SIGHUP while no command runs.
+static STOP: AtomicI32 = AtomicI32::new(0);
+
+/// Signals that can be given by name, without their `SIG` prefix.
+const SIGNAL_NAMES: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+    ("WINCH", libc::SIGWINCH),
+    ("ALRM", libc::SIGALRM),
+];
+
+/// Parses a signal given by name, with or without the `SIG` prefix (e.g.
+/// `SIGHUP`, `hup`), or by number.
+pub fn parse_signal(spec: &str) -> Result<libc::c_int, String> {
+    if let Ok(number) = spec.parse::<libc::c_int>() {
+        return match number {
+            1..=31 => Ok(number),
+            _ => Err(format!("'{}' is not a signal number", spec)),
+        };
+    }
+    let upper = spec.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNAL_NAMES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, signal)| *signal)
+        .ok_or_else(|| {
+            let names: Vec<String> = SIGNAL_NAMES
+                .iter()
+                .map(|(known, _)| format!("SIG{}", known))
+                .collect();
+            format!("unknown signal '{}' (use {})", spec, names.join(", "))
+        })
+}
+
+extern "C" fn forward(signal: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if matches!(signal, libc::SIGINT | libc::SIGTERM | libc::SIGQUIT)
+        || (signal == libc::SIGHUP && pid <= 0)
+    {
+        STOP.store(signal, Ordering::SeqCst);
+    }
+    if pid <= 0 {
+        return;
+    }
+    // SAFETY: The kernel passes a valid siginfo to SA_SIGINFO handlers.
+    // A positive code means the kernel sent the signal, e.g. the terminal.
+    let sent_by_process = unsafe { (*info).si_code } <= 0;
+    // SAFETY: kill is async-signal-safe.
+    unsafe {
+        if OWN_GROUP.load(Ordering::SeqCst) {
+            // The terminal no longer reaches the group, so Ctrl-C goes too
+            libc::kill(-pid, signal);
+        } else if signal != libc::SIGINT || sent_by_process {
+            // An interactive Ctrl-C reaches the command directly, through
+            // the terminal's process group; sending it again could
+            // force-quit it. rbwchain alone gets the SIGINT of `kill -INT`
+            // or `docker kill -s INT`.
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+/// Passes SIGTERM and SIGHUP on to the process `pid` (its whole process
+/// group, with `own_group`) instead of letting them terminate rbwchain.
+/// SIGINT is passed on only if another process sent it, as the terminal's
+/// Ctrl-C reaches the command by itself, or always to the group.
+///
+/// As an `init`, SIGQUIT, SIGUSR1, SIGUSR2 and SIGWINCH are passed on too,
+/// since a container's PID 1 ignores any signal it has no handler for.
+pub fn forward_to(pid: u32, own_group: bool, init: bool) {
+    CHILD_PID.store(pid as i32, Ordering::SeqCst);
+    OWN_GROUP.store(own_group, Ordering::SeqCst);
+    let mut signals = vec![libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+    if init {
+        signals.extend([libc::SIGQUIT, libc::SIGUSR1, libc::SIGUSR2, libc::SIGWINCH]);
+    }
+    install(&signals);
+}
+
+/// Records SIGINT, SIGTERM and SIGHUP as a request to stop instead of
+/// letting them terminate rbwchain, before it writes the files that must
+/// be removed again. Nothing is passed on until [`forward_to`].
+pub fn record_stops() {
+    install(&[libc::SIGINT, libc::SIGTERM, libc::SIGHUP]);
+}
+
+fn install(signals: &[libc::c_int]) {
+    for &signal in signals {
+        // SAFETY: `forward` only performs async-signal-safe operations, and
+        // the zeroed sigaction is a valid value with an empty mask.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = forward
+                as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+                as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            action.sa_flags = libc::SA_RESTART | libc::SA_SIGINFO;
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Stops passing signals on, once the command has exited and its process id
+/// may be reused. The handlers stay installed.
+pub fn stop_forwarding() {
+    CHILD_PID.store(0, Ordering::SeqCst);
+}
+
+/// Sends `signal` to the command the way a forwarded one would go, without
+/// asking rbwchain itself to stop. Does nothing when no command runs.
+pub fn send(signal: libc::c_int) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if pid <= 0 {
+        return;
+    }
+    let target = if OWN_GROUP.load(Ordering::SeqCst) {
+        -pid
+    } else {
+        pid
+    };
+    // SAFETY: kill has no memory safety preconditions.
+    unsafe { libc::kill(target, signal) };
+}
+
+/// Whether a signal asked rbwchain to stop since its handlers were
+/// installed, so a supervisor does not run the command again.
+pub fn stop_requested() -> bool {
+    stop_signal().is_some()
+}
+
+/// The signal that asked rbwchain to stop, if any.
+pub fn stop_signal() -> Option<libc::c_int> {
+    match STOP.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
@@ -0,0 +1,124 @@
+//! Backend decrypting local age-encrypted files, for offline or bootstrap
+//! scenarios where no password manager is available.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{run_capture, SecretSource, SourceConfig, SourceError};
+
+/// Decrypts `SECRET_NOTE`, interpreted as a path to an `.age` file.
+pub struct AgeSource {
+    debug: bool,
+}
+
+impl AgeSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(AgeSource {
+            debug: config.debug,
+        })
+    }
+}
+
+/// Maps age's stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("no identity matched") || stderr.contains("incorrect passphrase") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+/// Resolves the identity files to decrypt with.
+/// `AGE_IDENTITY` (a `:`-separated list of paths) takes precedence;
+/// otherwise every file in `$XDG_CONFIG_HOME/age` (default `~/.config/age`) is used.
+fn identity_files() -> Result<Vec<PathBuf>, SourceError> {
+    if let Some(identities) = env::var_os("AGE_IDENTITY").filter(|v| !v.is_empty()) {
+        return Ok(env::split_paths(&identities).collect());
+    }
+
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("age"))
+        .ok_or_else(|| {
+            SourceError::Unavailable("Cannot determine the age config directory.".to_string())
+        })?;
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&config_dir)
+        .map_err(|e| {
+            SourceError::Unavailable(format!(
+                "No age identities: set AGE_IDENTITY or create '{}' ({})",
+                config_dir.display(),
+                e
+            ))
+        })?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    if files.is_empty() {
+        return Err(SourceError::Unavailable(format!(
+            "No age identities found in '{}'.",
+            config_dir.display()
+        )));
+    }
+    Ok(files)
+}
+
+impl SecretSource for AgeSource {
+    fn name(&self) -> &'static str {
+        "age"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("age").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'age' command was not found in your system's PATH. \
+Please ensure age (https://age-encryption.org) is installed and accessible."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Executes `age --decrypt -i <identity>... <secret>` and returns the plaintext.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        if !Path::new(secret).is_file() {
+            return Err(SourceError::NotFound(format!(
+                "Encrypted file '{}' does not exist.",
+                secret
+            )));
+        }
+
+        let mut command = Command::new("age");
+        command.arg("--decrypt");
+        for identity in identity_files()? {
+            debug_eprintln(
+                self.debug,
+                format_args!("Using age identity: {}", identity.display()),
+            );
+            command.arg("-i").arg(OsString::from(identity));
+        }
+        command.arg(secret);
+
+        let display = format!("age --decrypt {}", secret); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        run_capture(&mut command, &display, classify)
+    }
+
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Err(SourceError::Unsupported(
+            "The age source decrypts individual files and cannot list secrets.".to_string(),
+        ))
+    }
+}
@@ -0,0 +1,130 @@
+//! Backend for Bitwarden Secrets Manager, via the `bws` CLI.
+//!
+//! `bws` authenticates with the `BWS_ACCESS_TOKEN` environment variable,
+//! which is passed through untouched.
+
+use std::process::Command;
+
+use serde_json::Value;
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{render_env_lines, run_capture, SecretSource, SourceConfig, SourceError};
+
+/// Selects every secret of the project when used as SECRET_NOTE.
+const ALL_SECRETS: &str = "*";
+
+/// Fetches secrets through `bws secret get` / `bws secret list`.
+pub struct BwsSource {
+    debug: bool,
+    project: Option<String>,
+}
+
+impl BwsSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(BwsSource {
+            debug: config.debug,
+            project: config.project.clone(),
+        })
+    }
+
+    /// Runs `bws` with `args` and parses its JSON output.
+    fn run_json(&self, args: &[&str]) -> Result<Value, SourceError> {
+        let display = format!("bws {}", args.join(" ")); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        let stdout = run_capture(
+            Command::new("bws").args(args).args(["--output", "json"]),
+            &display,
+            classify,
+        )?;
+        serde_json::from_str(&stdout).map_err(|e| {
+            SourceError::Failed(format!("Output of '{}' is not valid JSON: {}", display, e))
+        })
+    }
+
+    /// Lists `(key, value)` pairs of the configured project, or of every
+    /// project the access token can read if none is configured.
+    fn secrets(&self) -> Result<Vec<(String, String)>, SourceError> {
+        let mut args = vec!["secret", "list"];
+        if let Some(project) = &self.project {
+            args.push(project);
+        }
+        let listing = self.run_json(&args)?;
+        let entries = listing.as_array().ok_or_else(|| {
+            SourceError::Failed("Unexpected 'bws secret list' output: expected an array.".into())
+        })?;
+        Ok(entries.iter().filter_map(key_value).collect())
+    }
+}
+
+/// Extracts the `key` and `value` fields of a bws secret object.
+fn key_value(secret: &Value) -> Option<(String, String)> {
+    Some((
+        secret.get("key")?.as_str()?.to_string(),
+        secret.get("value")?.as_str()?.to_string(),
+    ))
+}
+
+/// Maps bws' stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("not found") || stderr.contains("404") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("access token") || stderr.contains("unauthorized") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+impl SecretSource for BwsSource {
+    fn name(&self) -> &'static str {
+        "bws"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("bws").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'bws' command was not found in your system's PATH. \
+Please ensure the Bitwarden Secrets Manager CLI is installed and accessible."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// With `--project`, `*` renders every secret of the project as
+    /// `KEY=VALUE` lines and any other name returns that secret's raw value.
+    /// Without a project, `secret` is a secret id passed to `bws secret get`.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        if self.project.is_none() {
+            let fetched = self.run_json(&["secret", "get", secret])?;
+            return key_value(&fetched).map(|(_, value)| value).ok_or_else(|| {
+                SourceError::Failed("Unexpected 'bws secret get' output.".to_string())
+            });
+        }
+
+        let secrets = self.secrets()?;
+        if secret != ALL_SECRETS {
+            return secrets
+                .into_iter()
+                .find(|(key, _)| key == secret)
+                .map(|(_, value)| value)
+                .ok_or_else(|| {
+                    SourceError::NotFound(format!(
+                        "No secret named '{}' in project '{}'.",
+                        secret,
+                        self.project.as_deref().unwrap_or_default()
+                    ))
+                });
+        }
+
+        Ok(render_env_lines(secrets))
+    }
+
+    /// Returns the keys of the configured project's secrets.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Ok(self.secrets()?.into_iter().map(|(key, _)| key).collect())
+    }
+}
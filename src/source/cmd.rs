@@ -0,0 +1,59 @@
+//! Backend running an arbitrary command and using its stdout as the secret,
+//! mostly useful in mixed setups addressed through `cmd://` URIs.
+
+use std::process::Command;
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{run_capture, SecretSource, SourceConfig, SourceError};
+
+/// Runs `SECRET_NOTE` as a whitespace-separated command line (no shell).
+pub struct CmdSource {
+    debug: bool,
+}
+
+impl CmdSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(CmdSource {
+            debug: config.debug,
+        })
+    }
+}
+
+/// Any failure of an arbitrary command is a generic one.
+fn classify(_stderr: &str, message: String) -> SourceError {
+    SourceError::Failed(message)
+}
+
+impl SecretSource for CmdSource {
+    fn name(&self) -> &'static str {
+        "cmd"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let mut words = secret.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| SourceError::Failed("The cmd source needs a command.".to_string()))?;
+        if which(program).is_err() {
+            return Err(SourceError::Unavailable(format!(
+                "The '{}' command was not found.",
+                program
+            )));
+        }
+        debug_eprintln(self.debug, format_args!("Running '{}'", secret));
+        run_capture(Command::new(program).args(words), secret, classify)
+    }
+
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Err(SourceError::Unsupported(
+            "The cmd source runs commands and cannot list secrets.".to_string(),
+        ))
+    }
+}
@@ -0,0 +1,51 @@
+//! Backend re-exporting variables of rbwchain's own environment, mostly
+//! useful in mixed setups addressed through `env://` URIs.
+
+use std::env;
+
+use super::{render_env_lines, SecretSource, SourceConfig, SourceError};
+
+/// Selects parent variables starting with `SECRET_NOTE` and renders them as
+/// `KEY=VALUE` lines with that prefix stripped (`env://PROD_` turns
+/// `PROD_DB_URL` into `DB_URL`).
+pub struct EnvSource;
+
+impl EnvSource {
+    pub fn boxed(_config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(EnvSource)
+    }
+}
+
+impl SecretSource for EnvSource {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let mut vars: Vec<(String, String)> = env::vars()
+            .filter_map(|(key, value)| {
+                let stripped = key.strip_prefix(secret)?;
+                (!stripped.is_empty()).then(|| (stripped.to_string(), value))
+            })
+            .collect();
+        if vars.is_empty() {
+            return Err(SourceError::NotFound(format!(
+                "No environment variables start with '{}'.",
+                secret
+            )));
+        }
+        vars.sort();
+        Ok(render_env_lines(vars))
+    }
+
+    /// Returns the names of all variables in the environment.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        let mut names: Vec<String> = env::vars().map(|(key, _)| key).collect();
+        names.sort();
+        Ok(names)
+    }
+}
@@ -0,0 +1,111 @@
+//! Backend delegating to an external helper program, so in-house secret
+//! stores can be integrated without patching rbwchain.
+//!
+//! Protocol (`--source exec:/path/to/helper`):
+//!
+//! * `helper get <SECRET_NOTE>` prints the secret content to stdout, either
+//!   `KEY=VALUE` lines or raw content for file mode.
+//! * `helper list` prints one secret name per line.
+//! * Exit status `0` means success, `2` not found, `3` locked or
+//!   unauthenticated; anything else is a generic failure.
+//!
+//! The helper's stdin and stderr are passed through, so it may prompt for
+//! an answer or log freely.
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{SecretSource, SourceConfig, SourceError};
+
+/// The `--source` prefix selecting this backend.
+pub const PREFIX: &str = "exec:";
+
+/// Exit status a helper uses to report an unknown secret.
+const EXIT_NOT_FOUND: i32 = 2;
+/// Exit status a helper uses to report a locked store.
+const EXIT_LOCKED: i32 = 3;
+
+/// Runs a user-supplied helper following the protocol above.
+pub struct ExecSource {
+    debug: bool,
+    helper: PathBuf,
+}
+
+impl ExecSource {
+    pub fn boxed(helper: &str, config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(ExecSource {
+            debug: config.debug,
+            helper: PathBuf::from(helper),
+        })
+    }
+
+    /// Runs the helper with `args` and returns its stdout as UTF-8.
+    fn run(&self, args: &[&str]) -> Result<String, SourceError> {
+        let display = format!("{} {}", self.helper.display(), args.join(" ")); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        let output = Command::new(&self.helper)
+            .args(args)
+            .stdin(Stdio::inherit()) // Helpers may prompt for an answer
+            .stdout(Stdio::piped()) // Capture stdout
+            .stderr(Stdio::inherit()) // Helpers may prompt or log
+            .output()
+            .map_err(|e| {
+                SourceError::Unavailable(format!("Failed to execute '{}': {}", display, e))
+            })?;
+
+        if !output.status.success() {
+            let message = format!("Command '{}' failed with status {}", display, output.status);
+            return Err(match output.status.code() {
+                Some(EXIT_NOT_FOUND) => SourceError::NotFound(message),
+                Some(EXIT_LOCKED) => SourceError::Locked(message),
+                _ => SourceError::Failed(message),
+            });
+        }
+
+        String::from_utf8(output.stdout).map_err(|e| {
+            SourceError::Failed(format!("Output of '{}' is not valid UTF-8: {}", display, e))
+        })
+    }
+}
+
+impl SecretSource for ExecSource {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        // Bare names are looked up in PATH, anything else must exist as given
+        let found = if self.helper.components().count() == 1 {
+            which(self.helper.as_os_str()).is_ok()
+        } else {
+            self.helper.is_file()
+        };
+        if !found {
+            return Err(SourceError::Unavailable(format!(
+                "The plugin helper '{}' was not found.",
+                self.helper.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Executes `helper get <secret>` and returns its stdout.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        self.run(&["get", secret])
+    }
+
+    /// Executes `helper list` and returns one secret name per line.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Ok(self
+            .run(&["list"])?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
@@ -0,0 +1,50 @@
+//! Backend reading secrets from a local plaintext file, mostly useful in
+//! mixed setups addressed through `file://` URIs.
+
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::debug_eprintln;
+
+use super::{SecretSource, SourceConfig, SourceError};
+
+/// Reads `SECRET_NOTE`, interpreted as a file path.
+pub struct FileSource {
+    debug: bool,
+}
+
+impl FileSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(FileSource {
+            debug: config.debug,
+        })
+    }
+}
+
+impl SecretSource for FileSource {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        debug_eprintln(self.debug, format_args!("Reading file '{}'", secret));
+        fs::read_to_string(secret).map_err(|e| {
+            let message = format!("Failed to read '{}': {}", secret, e);
+            match e.kind() {
+                ErrorKind::NotFound => SourceError::NotFound(message),
+                ErrorKind::PermissionDenied => SourceError::Locked(message),
+                _ => SourceError::Failed(message),
+            }
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Err(SourceError::Unsupported(
+            "The file source reads individual files and cannot list secrets.".to_string(),
+        ))
+    }
+}
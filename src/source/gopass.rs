@@ -0,0 +1,112 @@
+//! Backend for `gopass`, which stores a password line followed by an
+//! optional YAML or `key: value` body.
+
+use std::process::Command;
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{render_env_lines, run_capture, SecretSource, SourceConfig, SourceError};
+
+/// The variable the first (password) line of an entry is injected as.
+const PASSWORD_KEY: &str = "PASSWORD";
+
+/// Fetches entries through `gopass show`.
+pub struct GopassSource {
+    debug: bool,
+}
+
+impl GopassSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(GopassSource {
+            debug: config.debug,
+        })
+    }
+}
+
+/// Maps gopass' stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("entry is not in the password store") || stderr.contains("not found") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("decryption failed") || stderr.contains("no secret key") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+/// Splits a gopass entry into `(key, value)` pairs: the password line becomes
+/// [`PASSWORD_KEY`], body lines in `key: value` or `KEY=VALUE` form are kept
+/// as-is. YAML document markers and lines without a separator are ignored.
+fn entry_pairs(content: &str) -> Vec<(String, String)> {
+    let mut lines = content.lines();
+    let mut pairs = Vec::new();
+    if let Some(password) = lines.next().filter(|line| !line.is_empty()) {
+        pairs.push((PASSWORD_KEY.to_string(), password.to_string()));
+    }
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line == "---" {
+            continue;
+        }
+        let separator = match (line.find(':'), line.find('=')) {
+            (Some(colon), Some(equals)) => colon.min(equals),
+            (Some(colon), None) => colon,
+            (None, Some(equals)) => equals,
+            (None, None) => continue,
+        };
+        let key = line[..separator].trim();
+        let value = line[separator + 1..].trim();
+        if !key.is_empty() {
+            pairs.push((key.to_string(), value.to_string()));
+        }
+    }
+    pairs
+}
+
+impl SecretSource for GopassSource {
+    fn name(&self) -> &'static str {
+        "gopass"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("gopass").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'gopass' command was not found in your system's PATH. \
+Please ensure gopass (https://www.gopass.pw) is installed and accessible."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Executes `gopass show -f <secret>` and renders the password line and
+    /// body keys as `KEY=VALUE` lines.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let display = format!("gopass show -f {}", secret); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        let stdout = run_capture(
+            Command::new("gopass").args(["show", "-f"]).arg(secret),
+            &display,
+            classify,
+        )?;
+        Ok(render_env_lines(entry_pairs(&stdout)))
+    }
+
+    /// Executes `gopass ls --flat` and returns one entry name per line.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        let stdout = run_capture(
+            Command::new("gopass").args(["ls", "--flat"]),
+            "gopass ls --flat",
+            classify,
+        )?;
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
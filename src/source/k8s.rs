@@ -0,0 +1,122 @@
+//! Backend reading Kubernetes Secrets through `kubectl`, handy for running
+//! workloads locally with the real cluster secrets.
+
+use std::process::Command;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use which::which;
+
+use crate::{debug_eprintln, warn_eprintln};
+
+use super::{render_env_lines, run_capture, SecretSource, SourceConfig, SourceError};
+
+/// Fetches `[NAMESPACE/]NAME` Secrets and decodes their `data` keys.
+pub struct K8sSource {
+    debug: bool,
+}
+
+impl K8sSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(K8sSource {
+            debug: config.debug,
+        })
+    }
+}
+
+/// Maps kubectl's stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("notfound") || stderr.contains("not found") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("forbidden")
+        || stderr.contains("unauthorized")
+        || stderr.contains("must be logged in")
+    {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+impl SecretSource for K8sSource {
+    fn name(&self) -> &'static str {
+        "k8s"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("kubectl").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'kubectl' command was not found in your system's PATH. \
+Please ensure kubectl is installed and configured for your cluster."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Executes `kubectl get secret NAME [-n NAMESPACE] -o json` and renders
+    /// every base64-decoded `data` key as a `KEY=VALUE` line.
+    /// Without a namespace, the current context's namespace is used.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let mut command = Command::new("kubectl");
+        command.arg("get").arg("secret");
+        match secret.split_once('/') {
+            Some((namespace, name)) => command.arg(name).arg("-n").arg(namespace),
+            None => command.arg(secret),
+        };
+        command.args(["-o", "json"]);
+
+        let display = format!("kubectl get secret {}", secret); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        let stdout = run_capture(&mut command, &display, classify)?;
+
+        let manifest: Value = serde_json::from_str(&stdout).map_err(|e| {
+            SourceError::Failed(format!("Output of '{}' is not valid JSON: {}", display, e))
+        })?;
+        let Some(data) = manifest.get("data").and_then(Value::as_object) else {
+            // A Secret without data is valid, it just has nothing to inject
+            return Ok(String::new());
+        };
+
+        let mut pairs = Vec::new();
+        for (key, encoded) in data {
+            let decoded = encoded
+                .as_str()
+                .and_then(|s| STANDARD.decode(s).ok())
+                .and_then(|bytes| String::from_utf8(bytes).ok());
+            match decoded {
+                Some(value) => pairs.push((key.clone(), value)),
+                None => warn_eprintln(
+                    self.debug,
+                    format_args!("Skipping key '{}': not base64-encoded UTF-8.", key),
+                ),
+            }
+        }
+        Ok(render_env_lines(pairs))
+    }
+
+    /// Lists Secrets across all namespaces as `NAMESPACE/NAME`.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        let stdout = run_capture(
+            Command::new("kubectl").args([
+                "get",
+                "secrets",
+                "--all-namespaces",
+                "--no-headers",
+                "-o",
+                "custom-columns=NS:.metadata.namespace,NAME:.metadata.name",
+            ]),
+            "kubectl get secrets --all-namespaces",
+            classify,
+        )?;
+        Ok(stdout
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                Some(format!("{}/{}", columns.next()?, columns.next()?))
+            })
+            .collect())
+    }
+}
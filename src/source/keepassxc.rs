@@ -0,0 +1,367 @@
+//! Backend reading entries from a KeePassXC database through `keepassxc-cli`.
+//!
+//! The database password is prompted for once and fed to every keepassxc-cli
+//! call on stdin, so fetching several attributes or attachments unlocks the
+//! database without asking again.
+
+use std::cell::OnceCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{render_env_lines, SecretSource, SourceConfig, SourceError};
+
+/// Fetches entry attributes (or a single attachment) from a `.kdbx` file.
+pub struct KeepassxcSource {
+    debug: bool,
+    database: Option<PathBuf>,
+    attachment: Option<String>,
+    /// Read on the first call that needs it.
+    password: OnceCell<String>,
+}
+
+impl KeepassxcSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(KeepassxcSource {
+            debug: config.debug,
+            database: config.database.clone(),
+            attachment: config.attachment.clone(),
+            password: OnceCell::new(),
+        })
+    }
+
+    /// Returns the database password, prompting for it on the first call.
+    fn password(&self) -> Result<&str, SourceError> {
+        if let Some(password) = self.password.get() {
+            return Ok(password);
+        }
+        let prompt = format!("Password to unlock {}: ", self.database()?.display());
+        let password = read_password(&prompt).map_err(|e| {
+            SourceError::Failed(format!("Failed to read the database password: {}", e))
+        })?;
+        Ok(self.password.get_or_init(|| password))
+    }
+
+    fn database(&self) -> Result<&PathBuf, SourceError> {
+        self.database.as_ref().ok_or_else(|| {
+            SourceError::Unavailable(
+                "The keepassxc source requires --db <PATH> to the .kdbx database.".to_string(),
+            )
+        })
+    }
+
+    /// Runs keepassxc-cli with `args` and returns stdout as UTF-8.
+    fn run(&self, args: &[&std::ffi::OsStr]) -> Result<String, SourceError> {
+        let stdout = self.run_bytes(args)?;
+        String::from_utf8(stdout).map_err(|e| {
+            SourceError::Failed(format!(
+                "Output of 'keepassxc-cli {}' is not valid UTF-8: {}",
+                args[0].to_string_lossy(),
+                e
+            ))
+        })
+    }
+
+    /// Runs keepassxc-cli with `args` like [`Self::run`], returning stdout
+    /// as-is, for binary attachments.
+    fn run_bytes(&self, args: &[&std::ffi::OsStr]) -> Result<Vec<u8>, SourceError> {
+        let output = self.output(args)?;
+        if !output.status.success() {
+            return Err(failure(args, &output));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Runs keepassxc-cli with `args`, writing the database password to its
+    /// stdin, and returns the captured output whatever the exit status.
+    fn output(&self, args: &[&std::ffi::OsStr]) -> Result<Output, SourceError> {
+        let password = self.password()?;
+        let display = command_display(args);
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        let mut child = Command::new("keepassxc-cli")
+            .args(args)
+            .stdin(Stdio::piped()) // The database password is read from here
+            .stdout(Stdio::piped()) // Capture stdout
+            .stderr(Stdio::piped()) // Capture its own prompt and errors
+            .spawn()
+            .map_err(|e| {
+                SourceError::Unavailable(format!("Failed to execute '{}': {}", display, e))
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A failed write shows up as an unlock error below
+            let _ = writeln!(stdin, "{}", password);
+        }
+        child
+            .wait_with_output()
+            .map_err(|e| SourceError::Failed(format!("Failed to wait for '{}': {}", display, e)))
+    }
+
+    /// Fetches the exact value of one attribute with `show -a`, which prints
+    /// it without a name prefix. Returns `None` if the entry has no such
+    /// attribute.
+    fn attribute(&self, secret: &str, name: &str) -> Result<Option<String>, SourceError> {
+        let args: [&std::ffi::OsStr; 6] = [
+            "show".as_ref(),
+            "--show-protected".as_ref(),
+            "--attributes".as_ref(),
+            name.as_ref(),
+            self.database()?.as_os_str(),
+            secret.as_ref(),
+        ];
+        let output = self.output(&args)?;
+        if !output.status.success() {
+            if String::from_utf8_lossy(&output.stderr).contains("unknown attribute") {
+                return Ok(None);
+            }
+            return Err(failure(&args, &output));
+        }
+        let mut value = String::from_utf8(output.stdout).map_err(|e| {
+            SourceError::Failed(format!("Attribute '{}' is not valid UTF-8: {}", name, e))
+        })?;
+        if value.ends_with('\n') {
+            value.pop();
+        }
+        Ok(Some(value))
+    }
+}
+
+fn command_display(args: &[&std::ffi::OsStr]) -> String {
+    format!(
+        "keepassxc-cli {}",
+        args.iter()
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Describes a failed keepassxc-cli run, including its error output.
+fn failure(args: &[&std::ffi::OsStr], output: &Output) -> SourceError {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Drop its password prompt, which is not followed by a newline
+    let message = stderr
+        .trim()
+        .strip_prefix("Enter password to unlock ")
+        .and_then(|rest| rest.split_once(": "))
+        .map_or(stderr.trim(), |(_, rest)| rest.trim());
+    SourceError::Failed(format!(
+        "Command '{}' failed with status {}: {}",
+        command_display(args),
+        output.status,
+        message
+    ))
+}
+
+/// Prompts for a password on the terminal with echo disabled. Without a
+/// terminal, the first line of stdin is read instead.
+fn read_password(prompt: &str) -> io::Result<String> {
+    let mut line = String::new();
+    match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(mut tty) => {
+            tty.write_all(prompt.as_bytes())?;
+            {
+                let _echo_off = EchoOff::enable(&tty)?;
+                BufReader::new(&tty).read_line(&mut line)?;
+            }
+            tty.write_all(b"\n")?;
+        }
+        Err(_) => {
+            io::stdin().lock().read_line(&mut line)?;
+        }
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Turns off terminal echo and restores it when dropped.
+struct EchoOff {
+    fd: i32,
+    original: libc::termios,
+}
+
+impl EchoOff {
+    fn enable(tty: &File) -> io::Result<EchoOff> {
+        let fd = tty.as_raw_fd();
+        // SAFETY: termios is plain data and fd is a valid open terminal.
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut silent = original;
+            silent.c_lflag &= !libc::ECHO;
+            silent.c_lflag |= libc::ECHONL;
+            if libc::tcsetattr(fd, libc::TCSANOW, &silent) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(EchoOff { fd, original })
+        }
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in `enable` on the same fd.
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Lists the possible attribute names in `keepassxc-cli show --all` output
+/// (`Attribute: value` lines), in order and without repeats. A line inside a
+/// multi-line value such as `Notes` can look the same, so these are only
+/// candidates, checked one by one with `show -a`.
+fn attribute_candidates(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for line in content.lines() {
+        if let Some((key, _)) = line.split_once(": ") {
+            if !key.is_empty()
+                && !key.contains(char::is_whitespace)
+                && !names.iter().any(|n| n == key)
+            {
+                names.push(key.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Extracts the attachment names from `keepassxc-cli show --show-attachments`
+/// output, where they are listed indented after an `Attachments:` line, each
+/// followed by its size in parentheses.
+fn attachment_names(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .skip_while(|line| line.trim() != "Attachments:")
+        .skip(1)
+        .take_while(|line| line.starts_with(char::is_whitespace))
+        .map(|line| {
+            let line = line.trim();
+            line.rsplit_once(" (").map_or(line, |(name, _)| name)
+        })
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl SecretSource for KeepassxcSource {
+    fn name(&self) -> &'static str {
+        "keepassxc"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("keepassxc-cli").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'keepassxc-cli' command was not found in your system's PATH. \
+Please ensure KeePassXC (https://keepassxc.org) is installed and accessible."
+                    .to_string(),
+            ));
+        }
+        let database = self.database()?;
+        if !database.is_file() {
+            return Err(SourceError::NotFound(format!(
+                "KeePassXC database '{}' does not exist.",
+                database.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Renders every attribute of entry `secret` as `KEY=VALUE` lines, or,
+    /// with `--attachment`, returns the raw content of that attachment.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let database = self.database()?.as_os_str();
+        if let Some(attachment) = &self.attachment {
+            let content = self.fetch_attachment(secret, attachment)?;
+            return String::from_utf8(content).map_err(|_| {
+                SourceError::Failed(format!(
+                    "Attachment '{}' is binary; write it to a file with -f.",
+                    attachment
+                ))
+            });
+        }
+        let stdout = self.run(&["show".as_ref(), "--all".as_ref(), database, secret.as_ref()])?;
+        let mut pairs = Vec::new();
+        for name in attribute_candidates(&stdout) {
+            if let Some(value) = self.attribute(secret, &name)? {
+                pairs.push((name, value));
+            }
+        }
+        Ok(render_env_lines(pairs))
+    }
+
+    /// Executes `keepassxc-cli show --show-attachments` and returns the names
+    /// of the entry's attachments.
+    fn list_attachments(&self, secret: &str) -> Result<Vec<String>, SourceError> {
+        let stdout = self.run(&[
+            "show".as_ref(),
+            "--show-attachments".as_ref(),
+            self.database()?.as_os_str(),
+            secret.as_ref(),
+        ])?;
+        Ok(attachment_names(&stdout))
+    }
+
+    /// Executes `keepassxc-cli attachment-export --stdout` and returns the
+    /// attachment's bytes.
+    fn fetch_attachment(&self, secret: &str, name: &str) -> Result<Vec<u8>, SourceError> {
+        self.run_bytes(&[
+            "attachment-export".as_ref(),
+            "--stdout".as_ref(),
+            self.database()?.as_os_str(),
+            secret.as_ref(),
+            name.as_ref(),
+            "-".as_ref(), // Output file, ignored with --stdout
+        ])
+    }
+
+    /// Executes `keepassxc-cli ls --recursive --flatten` on the database.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        let stdout = self.run(&[
+            "ls".as_ref(),
+            "--recursive".as_ref(),
+            "--flatten".as_ref(),
+            self.database()?.as_os_str(),
+        ])?;
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            // Group lines end with '/', only entries can be fetched
+            .filter(|line| !line.is_empty() && !line.ends_with('/'))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_keep_order_and_skip_repeats() {
+        let output = "Title: db\nUserName: admin\nNotes: first\nPassword: looks like one\nPassword: PROTECTED\n";
+        assert_eq!(
+            attribute_candidates(output),
+            ["Title", "UserName", "Notes", "Password"]
+        );
+    }
+
+    #[test]
+    fn candidates_skip_lines_that_cannot_be_names() {
+        let output = "Notes: line one\n  indented: text\nsee also: elsewhere\nnot a pair\n";
+        assert_eq!(attribute_candidates(output), ["Notes"]);
+    }
+
+    #[test]
+    fn attachment_names_drop_sizes() {
+        let output =
+            "Title: x\nAttachments:\n  client.p12 (2.1 KiB)\n  notes.txt (12 B)\nOther: y\n";
+        assert_eq!(attachment_names(output), ["client.p12", "notes.txt"]);
+    }
+}
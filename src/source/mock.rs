@@ -0,0 +1,39 @@
+//! Backend returning canned content without touching any store, for
+//! integration tests of wrapper scripts and dry runs in CI.
+
+use std::env;
+
+use super::{SecretSource, SourceConfig, SourceError};
+
+/// Setting this variable selects the mock source and provides its content.
+pub const CONTENT_VAR: &str = "RBWCHAIN_MOCK_CONTENT";
+
+/// Content returned when [`CONTENT_VAR`] is not set.
+const DEFAULT_CONTENT: &str = "MOCK_SECRET=mock-value\n";
+
+/// Returns [`CONTENT_VAR`] (or a fixed default) for every secret name.
+pub struct MockSource;
+
+impl MockSource {
+    pub fn boxed(_config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(MockSource)
+    }
+}
+
+impl SecretSource for MockSource {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    fn fetch(&self, _secret: &str) -> Result<String, SourceError> {
+        Ok(env::var(CONTENT_VAR).unwrap_or_else(|_| DEFAULT_CONTENT.to_string()))
+    }
+
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        Ok(Vec::new())
+    }
+}
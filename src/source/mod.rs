@@ -0,0 +1,335 @@
+//! Secret source backends.
+//!
+//! A secret source knows how to fetch the raw content of a named secret from
+//! some store (rbw, pass, ...). Backends are registered in [`REGISTRY`] and
+//! selected at runtime through the `--source` flag.
+
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+mod age;
+mod bws;
+mod cmd;
+mod env;
+mod exec;
+mod file;
+mod gopass;
+mod k8s;
+mod keepassxc;
+mod mock;
+mod pass;
+mod rbw;
+
+/// The source used when `--source` is not given.
+pub const DEFAULT_SOURCE: &str = "rbw";
+
+// --- Errors ---
+
+/// Classifies why a secret source failed, so callers can react to the kind
+/// of failure instead of matching on message text.
+#[derive(Debug)]
+pub enum SourceError {
+    /// The backend's tooling is not installed or not reachable.
+    Unavailable(String),
+    /// The requested secret does not exist in the store.
+    NotFound(String),
+    /// The store is locked or requires authentication.
+    Locked(String),
+    /// The backend does not support the requested operation.
+    Unsupported(String),
+    /// Any other failure (unexpected exit status, invalid output, ...).
+    Failed(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Unavailable(msg) => write!(f, "source unavailable: {}", msg),
+            SourceError::NotFound(msg) => write!(f, "secret not found: {}", msg),
+            SourceError::Locked(msg) => write!(f, "store is locked: {}", msg),
+            SourceError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            SourceError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for SourceError {}
+
+// --- Trait ---
+
+/// A backend capable of fetching secrets by name.
+pub trait SecretSource {
+    /// The name the backend is registered under (e.g. `rbw`).
+    fn name(&self) -> &'static str;
+
+    /// Verifies that the backend can be used at all (e.g. its CLI is in PATH).
+    fn check_available(&self) -> Result<(), SourceError>;
+
+    /// Fetches the raw content of `secret`.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError>;
+
+    /// Brings the backend's local copy of the secrets up to date with the
+    /// server (e.g. `rbw sync`). Backends reading them directly do nothing.
+    fn sync(&self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    /// When the local copy of the secrets was last synced, if the backend
+    /// keeps one and can tell.
+    fn last_sync(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// Fetches a single named field of `secret` (e.g. a custom field of a
+    /// Bitwarden item). Backends without fields report `Unsupported`.
+    fn fetch_field(&self, secret: &str, field: &str) -> Result<String, SourceError> {
+        let _ = (secret, field);
+        Err(SourceError::Unsupported(format!(
+            "The {} source does not support fields.",
+            self.name()
+        )))
+    }
+
+    /// Lists the names of the files attached to `secret`. Backends without
+    /// attachments report `Unsupported`.
+    fn list_attachments(&self, secret: &str) -> Result<Vec<String>, SourceError> {
+        let _ = secret;
+        Err(SourceError::Unsupported(format!(
+            "The {} source does not support attachments.",
+            self.name()
+        )))
+    }
+
+    /// Fetches the raw bytes of the attachment `name` of `secret`.
+    fn fetch_attachment(&self, secret: &str, name: &str) -> Result<Vec<u8>, SourceError> {
+        let _ = (secret, name);
+        Err(SourceError::Unsupported(format!(
+            "The {} source does not support attachments.",
+            self.name()
+        )))
+    }
+
+    /// Lists the names of the secrets known to the backend.
+    fn list(&self) -> Result<Vec<String>, SourceError>;
+
+    /// Lists the names of the secrets in `folder`. Backends without folders
+    /// report `Unsupported`.
+    fn list_folder(&self, folder: &str) -> Result<Vec<String>, SourceError> {
+        let _ = folder;
+        Err(SourceError::Unsupported(format!(
+            "The {} source does not support folders.",
+            self.name()
+        )))
+    }
+}
+
+// --- Registry ---
+
+/// Options shared by all backends, filled in from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct SourceConfig {
+    pub debug: bool,
+    /// The rbw binary to execute instead of `rbw` from PATH (`rbw` source).
+    pub rbw_path: Option<PathBuf>,
+    /// The rbw profile (`RBW_PROFILE`) to select (`rbw` source).
+    pub rbw_profile: Option<String>,
+    /// Look up whole items (`rbw get --raw`) and render their notes and
+    /// custom fields, as done when selecting by id or URI (`rbw` source).
+    pub raw_item: bool,
+    /// Restrict lookups to this folder (`rbw` source).
+    pub folder: Option<String>,
+    /// Read the username and password of login items (`rbw` source).
+    pub login: bool,
+    /// In login mode, also read the current TOTP code (`rbw` source).
+    pub totp: bool,
+    /// Bitwarden Secrets Manager project id (`bws` source).
+    pub project: Option<String>,
+    /// Path to the database file (`keepassxc` source).
+    pub database: Option<PathBuf>,
+    /// Fetch this attachment of the entry instead of its content.
+    pub attachment: Option<String>,
+}
+
+type Constructor = fn(&SourceConfig) -> Box<dyn SecretSource>;
+type PrefixedConstructor = fn(&str, &SourceConfig) -> Box<dyn SecretSource>;
+
+/// All known backends, keyed by the name accepted by `--source`.
+const REGISTRY: &[(&str, Constructor)] = &[
+    ("rbw", rbw::RbwSource::boxed),
+    ("pass", pass::PassSource::boxed),
+    ("age", age::AgeSource::boxed),
+    ("bws", bws::BwsSource::boxed),
+    ("k8s", k8s::K8sSource::boxed),
+    ("gopass", gopass::GopassSource::boxed),
+    ("keepassxc", keepassxc::KeepassxcSource::boxed),
+    ("file", file::FileSource::boxed),
+    ("cmd", cmd::CmdSource::boxed),
+    ("env", env::EnvSource::boxed),
+    ("mock", mock::MockSource::boxed),
+];
+
+/// Backends taking an argument as part of their name (`exec:/path/to/helper`),
+/// keyed by prefix.
+const PREFIXED_REGISTRY: &[(&str, PrefixedConstructor)] =
+    &[(exec::PREFIX, exec::ExecSource::boxed)];
+
+/// Returns the names of all registered backends.
+pub fn names() -> Vec<String> {
+    REGISTRY
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(
+            PREFIXED_REGISTRY
+                .iter()
+                .map(|(prefix, _)| format!("{}<ARG>", prefix)),
+        )
+        .collect()
+}
+
+/// Instantiates the backend registered under `name`.
+pub fn create(name: &str, config: &SourceConfig) -> Result<Box<dyn SecretSource>, SourceError> {
+    for (prefix, constructor) in PREFIXED_REGISTRY {
+        if let Some(argument) = name.strip_prefix(prefix) {
+            return Ok(constructor(argument, config));
+        }
+    }
+    REGISTRY
+        .iter()
+        .find(|(registered, _)| *registered == name)
+        .map(|(_, constructor)| constructor(config))
+        .ok_or_else(|| {
+            SourceError::Unavailable(format!(
+                "unknown source '{}' (available: {})",
+                name,
+                names().join(", ")
+            ))
+        })
+}
+
+/// Picks the source name and the reference handed to it for `secret_note`.
+///
+/// `RBWCHAIN_MOCK_CONTENT` in the environment forces the mock source, so CI
+/// never touches a real store. Otherwise a `scheme://reference` note names
+/// its source directly, and anything else is fetched from `flag_source`.
+pub fn resolve<'a>(secret_note: &'a str, flag_source: &'a str) -> (&'a str, &'a str) {
+    if mock_forced() {
+        let reference = parse_uri(secret_note).map_or(secret_note, |(_, r)| r);
+        return ("mock", reference);
+    }
+    parse_uri(secret_note).unwrap_or((flag_source, secret_note))
+}
+
+/// Picks the source to list notes from when there is no SECRET_NOTE yet,
+/// as for `--pick` and `--folder-env`. Like [`resolve`], the mock source
+/// wins when `RBWCHAIN_MOCK_CONTENT` is set.
+pub fn resolve_listing(flag_source: &str) -> &str {
+    if mock_forced() {
+        "mock"
+    } else {
+        flag_source
+    }
+}
+
+fn mock_forced() -> bool {
+    std::env::var_os(mock::CONTENT_VAR).is_some()
+}
+
+/// Splits a `scheme://reference` SECRET_NOTE into the registered source
+/// name and the reference. Unknown schemes are not treated as URIs.
+fn parse_uri(secret_note: &str) -> Option<(&str, &str)> {
+    let (scheme, reference) = secret_note.split_once("://")?;
+    REGISTRY
+        .iter()
+        .any(|(name, _)| *name == scheme)
+        .then_some((scheme, reference))
+}
+
+// --- Helpers for CLI-based backends ---
+
+/// Runs `command`, capturing its output, and returns stdout as UTF-8.
+/// `display` is used in error messages. On a non-zero exit status the
+/// captured stderr is handed to `classify` to pick the error kind.
+pub(crate) fn run_capture(
+    command: &mut Command,
+    display: &str,
+    classify: fn(&str, String) -> SourceError,
+) -> Result<String, SourceError> {
+    let stdout = run_capture_bytes(command, display, classify)?;
+    String::from_utf8(stdout).map_err(|e| {
+        SourceError::Failed(format!("Output of '{}' is not valid UTF-8: {}", display, e))
+    })
+}
+
+/// Runs `command` like [`run_capture`], returning stdout as-is, for
+/// binary content such as attachments.
+pub(crate) fn run_capture_bytes(
+    command: &mut Command,
+    display: &str,
+    classify: fn(&str, String) -> SourceError,
+) -> Result<Vec<u8>, SourceError> {
+    let output = command
+        .stdout(Stdio::piped()) // Capture stdout
+        .stderr(Stdio::piped()) // Capture stderr for error reporting
+        .output() // Execute and wait
+        .map_err(|e| SourceError::Unavailable(format!("Failed to execute '{}': {}", display, e)))?;
+
+    // Check if the command executed successfully
+    if !output.status.success() {
+        let stderr_output = String::from_utf8_lossy(&output.stderr);
+        let stderr_output = stderr_output.trim();
+        let message = format!(
+            "Command '{}' failed with status {}: {}",
+            display, output.status, stderr_output
+        );
+        return Err(classify(stderr_output, message));
+    }
+    Ok(output.stdout)
+}
+
+/// Maps a secret name to a valid environment variable name by replacing
+/// every character outside `[A-Za-z0-9_]` with `_`.
+pub(crate) fn env_var_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Renders key/value pairs of a structured store as `KEY=VALUE` lines, so
+/// they flow through the regular env-var parser. Keys are mapped with
+/// [`env_var_name`]; values the parser would not read back verbatim
+/// (multi-line, quoted, padded, or containing an inline comment or a
+/// `$` reference) are written as literal heredocs.
+pub(crate) fn render_env_lines(pairs: impl IntoIterator<Item = (String, String)>) -> String {
+    let mut content = String::new();
+    for (key, value) in pairs {
+        let key = env_var_name(&key);
+        let verbatim = value.trim() == value
+            && !value.contains('\n')
+            && !value.starts_with(['"', '\''])
+            && !value.contains(" #")
+            && !value.contains("\t#")
+            && !value.contains('$');
+        if verbatim {
+            content.push_str(&format!("{}={}\n", key, value));
+            continue;
+        }
+        // Pick a delimiter that does not occur as a line of the value
+        let mut delimiter = String::from("EOF");
+        while value.lines().any(|line| line.trim() == delimiter) {
+            delimiter.push('_');
+        }
+        content.push_str(&format!(
+            "{}<<'{}'\n{}\n{}\n",
+            key, delimiter, value, delimiter
+        ));
+    }
+    content
+}
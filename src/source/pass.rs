@@ -0,0 +1,111 @@
+//! Backend for the standard unix password manager, `pass`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{run_capture, SecretSource, SourceConfig, SourceError};
+
+/// Fetches entries through `pass show`.
+pub struct PassSource {
+    debug: bool,
+}
+
+impl PassSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(PassSource {
+            debug: config.debug,
+        })
+    }
+}
+
+/// Maps pass' stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("is not in the password store") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("decryption failed") || stderr.contains("no secret key") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+/// The password store root, honouring `PASSWORD_STORE_DIR`.
+fn store_dir() -> Option<PathBuf> {
+    env::var_os("PASSWORD_STORE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".password-store")))
+}
+
+/// Recursively collects `*.gpg` entries below `dir`, relative to `root`.
+fn collect_entries(root: &Path, dir: &Path, entries: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            // Skip the store's own git metadata and other hidden directories
+            if path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            collect_entries(root, &path, entries)?;
+        } else if path.extension().is_some_and(|ext| ext == "gpg") {
+            if let Ok(relative) = path.with_extension("").strip_prefix(root) {
+                entries.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+impl SecretSource for PassSource {
+    fn name(&self) -> &'static str {
+        "pass"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which("pass").is_err() {
+            return Err(SourceError::Unavailable(
+                "The 'pass' command was not found in your system's PATH. \
+Please ensure pass (https://www.passwordstore.org) is installed and accessible."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Executes `pass show <secret>` and returns its stdout.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        let display = format!("pass show {}", secret); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        run_capture(
+            Command::new("pass").arg("show").arg(secret),
+            &display,
+            classify,
+        )
+    }
+
+    /// Walks the password store and returns all entry names.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        let root = store_dir().ok_or_else(|| {
+            SourceError::Failed("Cannot determine the password store directory.".to_string())
+        })?;
+        let mut entries = Vec::new();
+        collect_entries(&root, &root, &mut entries).map_err(|e| {
+            SourceError::Failed(format!(
+                "Failed to read password store '{}': {}",
+                root.display(),
+                e
+            ))
+        })?;
+        entries.sort();
+        Ok(entries)
+    }
+}
@@ -0,0 +1,364 @@
+//! The default backend, backed by the `rbw` Bitwarden CLI.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::SystemTime;
+
+use serde_json::Value;
+use which::which;
+
+use crate::debug_eprintln;
+
+use super::{
+    render_env_lines, run_capture, run_capture_bytes, SecretSource, SourceConfig, SourceError,
+};
+
+/// The rbw binary used when no `--rbw-path` is given.
+const DEFAULT_RBW: &str = "rbw";
+/// Bitwarden's official CLI, which attachments are downloaded with, as rbw
+/// cannot download them.
+const BW: &str = "bw";
+
+/// Fetches secret notes through `rbw get`.
+pub struct RbwSource {
+    debug: bool,
+    program: PathBuf,
+    profile: Option<String>,
+    raw_item: bool,
+    folder: Option<String>,
+    login: bool,
+    totp: bool,
+}
+
+impl RbwSource {
+    pub fn boxed(config: &SourceConfig) -> Box<dyn SecretSource> {
+        Box::new(RbwSource {
+            debug: config.debug,
+            program: config
+                .rbw_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_RBW)),
+            profile: config.rbw_profile.clone(),
+            raw_item: config.raw_item,
+            folder: config.folder.clone(),
+            login: config.login,
+            totp: config.totp,
+        })
+    }
+
+    /// Builds an rbw invocation, selecting the configured profile.
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        if let Some(profile) = &self.profile {
+            command.env("RBW_PROFILE", profile);
+        }
+        command
+    }
+
+    /// rbw's cache directory, where it keeps the local copy of the vault:
+    /// `rbw`, or `rbw-PROFILE` for a profile, in the user's cache directory.
+    fn cache_dir(&self) -> Option<PathBuf> {
+        let profile = self
+            .profile
+            .clone()
+            .or_else(|| env::var("RBW_PROFILE").ok())
+            .filter(|profile| !profile.is_empty());
+        let name = match profile {
+            Some(profile) => format!("rbw-{}", profile),
+            None => "rbw".to_string(),
+        };
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let cache = if cfg!(target_os = "macos") {
+            home.map(|home| home.join("Library/Caches"))
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| home.map(|home| home.join(".cache")))
+        };
+        cache.map(|dir| dir.join(name))
+    }
+
+    /// Executes `rbw list --fields name,folder` and returns the entry names,
+    /// restricted to `folder` if given.
+    fn list_in(&self, folder: Option<&str>) -> Result<Vec<String>, SourceError> {
+        let display = format!("{} list --fields name,folder", self.program.display());
+        let stdout = run_capture(
+            self.command().args(["list", "--fields", "name,folder"]),
+            &display,
+            classify,
+        )?;
+        Ok(stdout
+            .lines()
+            .filter_map(|line| {
+                // Fields are tab-separated; items without a folder have an empty one
+                let (name, item_folder) = line.split_once('\t').unwrap_or((line, ""));
+                let name = name.trim();
+                let wanted = folder.is_none_or(|f| f == item_folder.trim());
+                (wanted && !name.is_empty()).then(|| name.to_string())
+            })
+            .collect())
+    }
+
+    /// Executes `rbw <args> [--folder FOLDER] <secret>` and returns its stdout.
+    fn run(&self, args: &[&str], secret: &str) -> Result<String, SourceError> {
+        let mut command = self.command();
+        command.args(args);
+        if let Some(folder) = &self.folder {
+            command.arg("--folder").arg(folder);
+        }
+        command.arg(secret);
+
+        let display = format!(
+            "{} {} {}{}",
+            self.program.display(),
+            args.join(" "),
+            self.folder
+                .as_ref()
+                .map(|f| format!("--folder {} ", f))
+                .unwrap_or_default(),
+            secret
+        ); // For error messages
+        debug_eprintln(
+            self.debug,
+            format_args!(
+                "Running '{}' (profile: {})",
+                display,
+                self.profile.as_deref().unwrap_or("<default>")
+            ),
+        );
+        run_capture(&mut command, &display, classify)
+    }
+
+    /// Looks up the id of item `secret` with `rbw get --raw`, so `bw` reads
+    /// the same item that rbw would.
+    fn item_id(&self, secret: &str) -> Result<String, SourceError> {
+        let stdout = self.run(&["get", "--raw"], secret)?;
+        let item: Value = serde_json::from_str(&stdout).map_err(|e| {
+            SourceError::Failed(format!(
+                "Output of 'rbw get --raw {}' is not valid JSON: {}",
+                secret, e
+            ))
+        })?;
+        item.get("id")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| SourceError::Failed(format!("Item '{}' has no id.", secret)))
+    }
+
+    /// Executes `bw <args>` without prompting and returns its stdout. `bw`
+    /// must be logged in to the same account and unlocked (`BW_SESSION`).
+    fn bw(&self, args: &[&str]) -> Result<Vec<u8>, SourceError> {
+        if which(BW).is_err() {
+            return Err(SourceError::Unavailable(
+                "Attachments are downloaded with Bitwarden's 'bw' CLI, which was not found \
+in your system's PATH. Please install it (https://bitwarden.com/help/cli/) and unlock it."
+                    .to_string(),
+            ));
+        }
+        let display = format!("{} {}", BW, args.join(" ")); // For error messages
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        run_capture_bytes(
+            Command::new(BW).args(args).arg("--nointeraction"),
+            &display,
+            classify_bw,
+        )
+    }
+}
+
+/// Renders an item from `rbw get --raw` output: its notes, followed by its
+/// custom fields as `KEY=VALUE` lines. Returns `None` if it has neither.
+fn render_item(item: &Value) -> Option<String> {
+    let mut content = String::new();
+    if let Some(notes) = item.get("notes").and_then(Value::as_str) {
+        content.push_str(notes);
+        content.push('\n');
+    }
+    let fields = item
+        .get("fields")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|field| {
+            Some((
+                field.get("name")?.as_str()?.to_string(),
+                field.get("value")?.as_str()?.to_string(),
+            ))
+        });
+    content.push_str(&render_env_lines(fields));
+    (!content.is_empty()).then_some(content)
+}
+
+/// Renders the credentials of a login item from `rbw get --raw` output as
+/// `USERNAME=` and `PASSWORD=` lines. Returns `None` for other item types.
+fn render_login(item: &Value) -> Option<String> {
+    let data = item.get("data")?;
+    let password = data.get("password")?.as_str()?;
+    let username = data.get("username").and_then(Value::as_str);
+    let pairs = username
+        .map(|username| ("USERNAME".to_string(), username.to_string()))
+        .into_iter()
+        .chain([("PASSWORD".to_string(), password.to_string())]);
+    Some(render_env_lines(pairs))
+}
+
+/// Maps rbw's stderr to an error kind.
+fn classify(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("no entry found") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("locked") || stderr.contains("not logged in") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+/// Maps bw's stderr to an error kind.
+fn classify_bw(stderr: &str, message: String) -> SourceError {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("not found") {
+        SourceError::NotFound(message)
+    } else if stderr.contains("locked") || stderr.contains("not logged in") {
+        SourceError::Locked(message)
+    } else {
+        SourceError::Failed(message)
+    }
+}
+
+/// Extracts the attachment file names from `bw get item` output.
+fn attachment_names(item: &Value) -> Vec<String> {
+    item.get("attachments")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|attachment| attachment.get("fileName")?.as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+impl SecretSource for RbwSource {
+    fn name(&self) -> &'static str {
+        "rbw"
+    }
+
+    fn check_available(&self) -> Result<(), SourceError> {
+        if which(&self.program).is_err() {
+            return Err(SourceError::Unavailable(format!(
+                "The '{}' command was not found in your system's PATH. \
+Please ensure rbw (https://github.com/doy/rbw) is installed and accessible.",
+                self.program.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Executes `rbw sync`, fetching the current vault from the server.
+    fn sync(&self) -> Result<(), SourceError> {
+        let display = format!("{} sync", self.program.display());
+        debug_eprintln(self.debug, format_args!("Running '{}'", display));
+        run_capture(self.command().arg("sync"), &display, classify).map(|_| ())
+    }
+
+    /// The time the vault database in rbw's cache (`SERVER:EMAIL.json`) was
+    /// last written, which every sync does.
+    fn last_sync(&self) -> Option<SystemTime> {
+        fs::read_dir(self.cache_dir()?)
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+            .max()
+    }
+
+    /// Executes `rbw get <secret>` and returns its stdout. For whole-item
+    /// lookups (`secret` being a name, id or URI), `rbw get --raw` is used
+    /// and the item rendered with [`render_item`], or [`render_login`] in
+    /// login mode.
+    fn fetch(&self, secret: &str) -> Result<String, SourceError> {
+        if !self.raw_item && !self.login {
+            return self.run(&["get"], secret);
+        }
+
+        let stdout = self.run(&["get", "--raw"], secret)?;
+        let item: Value = serde_json::from_str(&stdout).map_err(|e| {
+            SourceError::Failed(format!(
+                "Output of 'rbw get --raw {}' is not valid JSON: {}",
+                secret, e
+            ))
+        })?;
+
+        if !self.login {
+            return render_item(&item).ok_or_else(|| {
+                SourceError::Failed(format!("Item '{}' has no notes or fields to read.", secret))
+            });
+        }
+
+        let mut content = render_login(&item).ok_or_else(|| {
+            SourceError::Failed(format!("Item '{}' is not a login item.", secret))
+        })?;
+        if self.totp {
+            let code = self.run(&["code"], secret)?;
+            content.push_str(&render_env_lines([(
+                "TOTP".to_string(),
+                code.trim().to_string(),
+            )]));
+        }
+        Ok(content)
+    }
+
+    /// Executes `rbw get --field <field> <secret>` and returns the value.
+    fn fetch_field(&self, secret: &str, field: &str) -> Result<String, SourceError> {
+        let value = self.run(&["get", "--field", field], secret)?;
+        Ok(value.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Executes `bw get item` on the item's id and returns the names of its
+    /// attachments.
+    fn list_attachments(&self, secret: &str) -> Result<Vec<String>, SourceError> {
+        let id = self.item_id(secret)?;
+        let stdout = self.bw(&["get", "item", &id])?;
+        let item: Value = serde_json::from_slice(&stdout).map_err(|e| {
+            SourceError::Failed(format!(
+                "Output of 'bw get item {}' is not valid JSON: {}",
+                id, e
+            ))
+        })?;
+        Ok(attachment_names(&item))
+    }
+
+    /// Executes `bw get attachment <name> --itemid <id> --raw` and returns
+    /// the attachment's bytes.
+    fn fetch_attachment(&self, secret: &str, name: &str) -> Result<Vec<u8>, SourceError> {
+        let id = self.item_id(secret)?;
+        self.bw(&["get", "attachment", name, "--itemid", &id, "--raw"])
+    }
+
+    /// Executes `rbw list --fields name,folder` and returns the entry names,
+    /// restricted to the configured folder if any.
+    fn list(&self) -> Result<Vec<String>, SourceError> {
+        self.list_in(self.folder.as_deref())
+    }
+
+    /// Returns the names of the entries in `folder`.
+    fn list_folder(&self, folder: &str) -> Result<Vec<String>, SourceError> {
+        self.list_in(Some(folder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachment_names_come_from_bw_item() {
+        let item = serde_json::json!({
+            "id": "abc",
+            "attachments": [{"id": "1", "fileName": "keystore.jks"}, {"id": "2"}],
+        });
+        assert_eq!(attachment_names(&item), ["keystore.jks"]);
+        assert!(attachment_names(&serde_json::json!({"id": "abc"})).is_empty());
+    }
+}
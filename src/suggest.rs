@@ -0,0 +1,111 @@
+//! "Did you mean ...?" suggestions for notes that could not be found.
+
+/// How many suggestions are offered at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// How long a name must be for containing it to count as similar, so that
+/// a short note such as `db` does not match nearly every typo.
+const MIN_CONTAINED_LENGTH: usize = 3;
+
+/// Computes the Levenshtein edit distance between `a` and `b`,
+/// compared case-insensitively.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    // Single-row dynamic programming over b
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns the candidates closest to `name`, best first. A candidate is
+/// close if its edit distance is within a third of the name's length (at
+/// least 2), or, ranked after those, if one contains the other and the
+/// contained one is at least `MIN_CONTAINED_LENGTH` characters long.
+pub fn suggestions<'a>(name: &str, candidates: &'a [String]) -> Vec<&'a str> {
+    let threshold = (name.chars().count() / 3).max(2);
+    let lowered = name.to_lowercase();
+    let contains = |outer: &str, inner: &str| {
+        inner.chars().count() >= MIN_CONTAINED_LENGTH && outer.contains(inner)
+    };
+
+    let mut scored: Vec<(bool, usize, &str)> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            let distance = edit_distance(name, candidate);
+            if distance <= threshold {
+                return Some((false, distance, candidate.as_str()));
+            }
+            let candidate_lowered = candidate.to_lowercase();
+            (contains(&candidate_lowered, &lowered) || contains(&lowered, &candidate_lowered))
+                .then_some((true, distance, candidate.as_str()))
+        })
+        .collect();
+    scored.sort();
+    scored.dedup_by(|a, b| a.2 == b.2);
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("Prod-DB", "prod-db"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+    }
+
+    #[test]
+    fn close_names_first() {
+        let candidates = names(&["prod-db-x", "staging-db", "prod-db"]);
+        assert_eq!(
+            suggestions("prod-db1", &candidates),
+            ["prod-db", "prod-db-x"]
+        );
+    }
+
+    #[test]
+    fn containment_ranks_after_edit_distance() {
+        let candidates = names(&["github-token-ci", "githb"]);
+        assert_eq!(
+            suggestions("github", &candidates),
+            ["githb", "github-token-ci"]
+        );
+    }
+
+    #[test]
+    fn short_names_do_not_match_by_containment() {
+        let candidates = names(&["db", "a", "my-service-creds"]);
+        assert_eq!(
+            suggestions("my-servce-credz", &candidates),
+            ["my-service-creds"]
+        );
+        assert!(suggestions("unrelated-typo", &candidates).is_empty());
+    }
+
+    #[test]
+    fn at_most_three() {
+        let candidates = names(&["note1", "note2", "note3", "note4"]);
+        assert_eq!(suggestions("note", &candidates).len(), MAX_SUGGESTIONS);
+    }
+}
@@ -0,0 +1,195 @@
+//! Supervision of the command (`--restart`, `--retry-on`). Every attempt is a run of
+//! rbwchain of its own, so that it fetches the secrets anew and cleans up
+//! the files it wrote before the next one starts.
+
+use std::env;
+use std::io;
+use std::process::{Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::{child, debug_eprintln, error_eprintln, signals, RBWCHAIN_PREFIX};
+
+/// How often a stop request is checked for while waiting to restart.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How many times the command is retried for `--retry-on` exit codes when
+/// `--retries` is not given.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// When the command is run again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Restart {
+    /// After it failed: exited non-zero or was killed by a signal.
+    OnFailure,
+    /// Whenever it exited.
+    Always,
+}
+
+/// The delays between attempts (`--backoff MIN..MAX`): MIN after a first
+/// failure, doubling with every further one up to MAX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+}
+
+/// When and how often the command is run again.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// Run again after a failure, or after any exit (`--restart`).
+    pub restart: Option<Restart>,
+    /// Exit codes to run again after (`--retry-on`).
+    pub retry_on: Vec<i32>,
+    /// How many times at most to run again (`--retries`). Unlimited with
+    /// `restart`, DEFAULT_RETRIES otherwise.
+    pub retries: Option<u32>,
+    /// The delays between attempts.
+    pub backoff: Backoff,
+}
+
+impl Policy {
+    /// Whether a run that ended with `status` is followed by another.
+    fn wants_rerun(&self, status: ExitStatus) -> bool {
+        match self.restart {
+            Some(Restart::Always) => true,
+            Some(Restart::OnFailure) if !status.success() => true,
+            _ => status
+                .code()
+                .is_some_and(|code| self.retry_on.contains(&code)),
+        }
+    }
+
+    fn max_reruns(&self) -> Option<u32> {
+        self.retries
+            .or((self.restart.is_none()).then_some(DEFAULT_RETRIES))
+    }
+}
+
+/// Parses a backoff range such as `1s..60s`.
+pub fn parse_backoff(spec: &str) -> Result<Backoff, String> {
+    let (min, max) = spec
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range like 1s..60s, got '{}'", spec))?;
+    let (min, max) = (child::parse_duration(min)?, child::parse_duration(max)?);
+    if min > max {
+        return Err(format!("the minimum exceeds the maximum in '{}'", spec));
+    }
+    Ok(Backoff { min, max })
+}
+
+/// Runs rbwchain again with the same arguments, marked `--supervised`, for
+/// as long as `policy` asks for it, or until rbwchain is told to stop.
+/// The backoff starts over after an attempt that succeeded or lasted at
+/// least the maximum delay. Returns the last exit status.
+pub fn run(policy: &Policy, init: bool, debug: bool) -> io::Result<ExitStatus> {
+    let exe = env::current_exe()?;
+    let backoff = policy.backoff;
+    let mut delay = backoff.min;
+    let mut attempt = 1;
+    loop {
+        debug_eprintln(debug, format_args!("Starting attempt {}.", attempt));
+        let started = Instant::now();
+        let mut run = Command::new(&exe)
+            .arg("--supervised")
+            .args(env::args_os().skip(1))
+            .spawn()?;
+        signals::forward_to(run.id(), false, init);
+        let status = child::wait(&mut run, init)?;
+        signals::stop_forwarding();
+
+        if signals::stop_requested() || !policy.wants_rerun(status) {
+            return Ok(status);
+        }
+        if policy.max_reruns().is_some_and(|max| attempt > max) {
+            error_eprintln(format_args!(
+                "The command exited ({}); giving up after {} attempts.",
+                status, attempt
+            ));
+            return Ok(status);
+        }
+        if status.success() || started.elapsed() >= backoff.max {
+            delay = backoff.min;
+        }
+        eprintln!(
+            "{} The command exited ({}); restarting in {:?}.",
+            RBWCHAIN_PREFIX, status, delay
+        );
+        let deadline = Instant::now() + delay;
+        while Instant::now() < deadline {
+            if signals::stop_requested() {
+                return Ok(status);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        delay = (delay * 2).min(backoff.max);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn backoff_ranges() {
+        assert_eq!(
+            parse_backoff("1s..60s"),
+            Ok(Backoff {
+                min: Duration::from_secs(1),
+                max: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(
+            parse_backoff("500ms..500ms"),
+            Ok(Backoff {
+                min: Duration::from_millis(500),
+                max: Duration::from_millis(500),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_backoff_ranges() {
+        assert!(parse_backoff("1s")
+            .unwrap_err()
+            .contains("expected a range"));
+        assert!(parse_backoff("1m..30s")
+            .unwrap_err()
+            .contains("minimum exceeds"));
+        assert!(parse_backoff("1s..").is_err());
+        assert!(parse_backoff("x..1s").is_err());
+    }
+
+    #[test]
+    fn reruns() {
+        let backoff = parse_backoff("1s..1s").unwrap();
+        let (ok, failed, code_75) = (
+            ExitStatus::from_raw(0),
+            ExitStatus::from_raw(1 << 8),
+            ExitStatus::from_raw(75 << 8),
+        );
+        let on_failure = Policy {
+            restart: Some(Restart::OnFailure),
+            retry_on: Vec::new(),
+            retries: None,
+            backoff,
+        };
+        assert!(!on_failure.wants_rerun(ok));
+        assert!(on_failure.wants_rerun(failed));
+        assert_eq!(on_failure.max_reruns(), None);
+
+        let retry_on = Policy {
+            restart: None,
+            retry_on: vec![75],
+            retries: None,
+            backoff,
+        };
+        assert!(retry_on.wants_rerun(code_75));
+        assert!(!retry_on.wants_rerun(failed));
+        assert_eq!(retry_on.max_reruns(), Some(DEFAULT_RETRIES));
+    }
+}
@@ -0,0 +1,87 @@
+//! Rendering of local template files with `{{KEY}}` placeholders filled in
+//! from parsed secret values.
+
+use std::collections::HashMap;
+
+/// Whether `name` can be the key of a placeholder. Anything else between
+/// braces (e.g. `{{ .Values.x }}` of another template language) is left as-is.
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        && !name.starts_with('.')
+}
+
+/// Replaces every `{{KEY}}` (or `{{ KEY }}`) in `template` with the value of
+/// `KEY` in `vars`. Fails with the list of keys that have no value, so a typo
+/// does not silently produce an incomplete config.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut missing: Vec<&str> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+        let end = start + 2 + length + 2;
+        rendered.push_str(&rest[..start]);
+        if !is_placeholder_name(name) {
+            rendered.push_str(&rest[start..end]);
+        } else if let Some(value) = vars.get(name) {
+            rendered.push_str(value);
+        } else if !missing.contains(&name) {
+            missing.push(name);
+        }
+        rest = &rest[end..];
+    }
+    rendered.push_str(rest);
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "The template uses key(s) missing from the note: {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        let vars = vars(&[("DB_URL", "postgres://db"), ("api.key", "k")]);
+        assert_eq!(
+            render("url={{DB_URL}}\nkey={{ api.key }}{{DB_URL}}\n", &vars),
+            Ok("url=postgres://db\nkey=kpostgres://db\n".to_string())
+        );
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        let vars = vars(&[("A", "{{B}}"), ("B", "b")]);
+        assert_eq!(render("{{A}}", &vars), Ok("{{B}}".to_string()));
+    }
+
+    #[test]
+    fn foreign_placeholders_are_kept() {
+        let template = "{{ .Values.x }} {{}} {{a b}} {{ unclosed";
+        assert_eq!(render(template, &HashMap::new()), Ok(template.to_string()));
+    }
+
+    #[test]
+    fn missing_keys_are_listed_once() {
+        let error = render("{{A}} {{B}} {{A}} {{C}}", &vars(&[("B", "b")])).unwrap_err();
+        assert!(error.ends_with(": A, C"), "{}", error);
+    }
+}
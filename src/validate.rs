@@ -0,0 +1,165 @@
+//! Shape checks for injected values (`--validate KEY=TYPE`).
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
+
+/// The expected shape of a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Url,
+    Int,
+    Base64,
+    Uuid,
+}
+
+impl Kind {
+    const ALL: [(&'static str, Kind); 4] = [
+        ("url", Kind::Url),
+        ("int", Kind::Int),
+        ("base64", Kind::Base64),
+        ("uuid", Kind::Uuid),
+    ];
+
+    fn name(self) -> &'static str {
+        Kind::ALL
+            .iter()
+            .find(|(_, kind)| *kind == self)
+            .map_or("", |(name, _)| name)
+    }
+
+    /// Whether `value` has this shape.
+    pub fn matches(self, value: &str) -> bool {
+        match self {
+            Kind::Url => is_url(value),
+            Kind::Int => value.parse::<i128>().is_ok(),
+            Kind::Base64 => {
+                !value.is_empty()
+                    && (STANDARD.decode(value).is_ok() || URL_SAFE.decode(value).is_ok())
+            }
+            Kind::Uuid => is_uuid(value),
+        }
+    }
+}
+
+/// Parses a `--validate KEY=TYPE` rule.
+pub fn parse_rule(spec: &str) -> Result<(String, Kind), String> {
+    let (key, kind) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=TYPE, got '{}'", spec))?;
+    let kind = Kind::ALL
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(kind))
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| {
+            let names: Vec<&str> = Kind::ALL.iter().map(|(name, _)| *name).collect();
+            format!("unknown type '{}' (expected {})", kind, names.join(", "))
+        })?;
+    if key.is_empty() {
+        return Err(format!("empty key in '{}'", spec));
+    }
+    Ok((key.to_string(), kind))
+}
+
+/// Checks the values in `vars` against `rules` and describes every
+/// mismatch. Values are never included in the descriptions. Keys that are
+/// not set are left to `--require`.
+pub fn failures(rules: &[(String, Kind)], vars: &HashMap<OsString, OsString>) -> Vec<String> {
+    rules
+        .iter()
+        .filter_map(|(key, kind)| {
+            let value = vars.get(OsStr::new(key))?;
+            let valid = value.to_str().is_some_and(|v| kind.matches(v));
+            (!valid).then(|| format!("{} is not a valid {}", key, kind.name()))
+        })
+        .collect()
+}
+
+/// `scheme://rest`, with an RFC 3986 scheme and no whitespace.
+fn is_url(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return false;
+    };
+    let scheme_valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    scheme_valid && !rest.is_empty() && !rest.contains(char::is_whitespace)
+}
+
+/// The canonical 8-4-4-4-12 hex digit form.
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert!(Kind::Url.matches("https://example.com/path?q=1"));
+        assert!(Kind::Url.matches("postgres+ssl://user:pw@db:5432/app"));
+        assert!(!Kind::Url.matches("example.com"));
+        assert!(!Kind::Url.matches("https://"));
+        assert!(!Kind::Url.matches("1http://example.com"));
+        assert!(!Kind::Url.matches("ht tp://example.com"));
+        assert!(!Kind::Url.matches("https://exa mple.com"));
+    }
+
+    #[test]
+    fn ints() {
+        assert!(Kind::Int.matches("42"));
+        assert!(Kind::Int.matches("-7"));
+        assert!(!Kind::Int.matches("4.2"));
+        assert!(!Kind::Int.matches(" 42"));
+        assert!(!Kind::Int.matches(""));
+    }
+
+    #[test]
+    fn base64() {
+        assert!(Kind::Base64.matches("aGVsbG8="));
+        assert!(Kind::Base64.matches("-_-_"));
+        assert!(!Kind::Base64.matches("not base64!"));
+        assert!(!Kind::Base64.matches(""));
+    }
+
+    #[test]
+    fn uuids() {
+        assert!(Kind::Uuid.matches("3f2a1b4c-0d9e-4f8a-b7c6-5d4e3f2a1b0c"));
+        assert!(Kind::Uuid.matches("3F2A1B4C-0D9E-4F8A-B7C6-5D4E3F2A1B0C"));
+        assert!(!Kind::Uuid.matches("3f2a1b4c0d9e4f8ab7c65d4e3f2a1b0c"));
+        assert!(!Kind::Uuid.matches("3f2a1b4c-0d9e-4f8a-b7c6-5d4e3f2a1b0g"));
+        assert!(!Kind::Uuid.matches("3f2a1b4c-0d9e-4f8a-b7c6-5d4e3f2a1b0c-1"));
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(parse_rule("PORT=int"), Ok(("PORT".to_string(), Kind::Int)));
+        assert_eq!(parse_rule("ID=UUID"), Ok(("ID".to_string(), Kind::Uuid)));
+        assert!(parse_rule("PORT").is_err());
+        assert!(parse_rule("=int").is_err());
+        assert!(parse_rule("PORT=float").unwrap_err().contains("url, int"));
+    }
+
+    #[test]
+    fn failures_name_keys_but_not_values() {
+        let vars: HashMap<OsString, OsString> = [("PORT", "eighty"), ("URL", "https://x")]
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        let rules = [
+            ("PORT".to_string(), Kind::Int),
+            ("URL".to_string(), Kind::Url),
+            ("MISSING".to_string(), Kind::Int),
+        ];
+        assert_eq!(failures(&rules, &vars), ["PORT is not a valid int"]);
+    }
+}
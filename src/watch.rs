@@ -0,0 +1,120 @@
+//! Watching the notes for changes (`--watch`, `--refresh-file`), so that a
+//! rotated secret reaches a long-running command without anyone restarting
+//! it by hand.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{content_hash, error_eprintln, signals, warn_eprintln, RBWCHAIN_PREFIX};
+
+/// A background check of the notes, started with [`start`].
+pub struct Watcher {
+    changed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    /// Whether the notes changed, and the command was stopped for a restart.
+    pub fn changed(&self) -> bool {
+        self.changed.load(Ordering::SeqCst)
+    }
+
+    /// Ends the watch: whatever runs from now on, e.g. the --post hooks, is
+    /// left alone.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A digest of the contents of the notes, in order.
+pub fn digest(contents: &[Vec<u8>]) -> String {
+    content_hash(&contents.join(&0))
+}
+
+/// Rewrites the -f files with new contents of the notes.
+pub type Rewrite = Box<dyn Fn(&[Vec<u8>]) -> Result<(), String> + Send>;
+
+/// What is done about a change of the notes.
+pub enum OnChange {
+    /// Stop the command with SIGTERM, to be run again.
+    Restart,
+    /// Rewrite the -f files, and send the command `signal` if given so that
+    /// it rereads them.
+    Reload {
+        signal: Option<libc::c_int>,
+        rewrite: Rewrite,
+    },
+}
+
+/// Calls `fetch` every `interval` and compares the [`digest`] of what it
+/// returns with `baseline`. Once they differ, `on_change` says what
+/// happens: a restart ends the watch, a reload carries on from the new
+/// contents. Failed fetches are skipped, as the next one may well succeed.
+pub fn start<F>(
+    interval: Duration,
+    mut baseline: String,
+    fetch: F,
+    on_change: OnChange,
+    debug: bool,
+) -> Watcher
+where
+    F: Fn() -> Result<Vec<Vec<u8>>, String> + Send + 'static,
+{
+    let changed = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let watcher = Watcher {
+        changed: Arc::clone(&changed),
+        stopped: Arc::clone(&stopped),
+    };
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let contents = match fetch() {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn_eprintln(
+                    debug,
+                    format_args!("Failed to check the notes for changes: {}", e),
+                );
+                continue;
+            }
+        };
+        let new_digest = digest(&contents);
+        if new_digest == baseline || stopped.load(Ordering::SeqCst) {
+            continue;
+        }
+        match &on_change {
+            OnChange::Restart => {
+                eprintln!(
+                    "{} The secret changed; restarting the command.",
+                    RBWCHAIN_PREFIX
+                );
+                changed.store(true, Ordering::SeqCst);
+                signals::send(libc::SIGTERM);
+                return;
+            }
+            OnChange::Reload { signal, rewrite } => {
+                if let Err(e) = rewrite(&contents) {
+                    // The command keeps the old files; the next fetch tries again
+                    error_eprintln(format_args!("{}", e));
+                    continue;
+                }
+                baseline = new_digest;
+                let Some(signal) = *signal else {
+                    eprintln!("{} The secret changed; rewrote the files.", RBWCHAIN_PREFIX);
+                    continue;
+                };
+                eprintln!(
+                    "{} The secret changed; rewrote the files and signalled the command to reload.",
+                    RBWCHAIN_PREFIX
+                );
+                signals::send(signal);
+            }
+        }
+    });
+    watcher
+}